@@ -3,6 +3,7 @@
 //this is the folders name in which our shaders will be stored,
 //in case of changing it, change this path too
 const SHADER_DIR: &str = "shaders";
+// also used at runtime by shader_reload.rs
 const COMPUTE_SHADER_DIR: &str = "compute_shaders";
 
 // Ensure that we recompile when shaders are changed
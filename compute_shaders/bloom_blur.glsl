@@ -0,0 +1,35 @@
+#version 450
+
+// One direction of a separable gaussian blur, run horizontally and then vertically
+layout(constant_id = 0) const int canvas_size_x = 1;
+layout(constant_id = 1) const int canvas_size_y = 1;
+layout(local_size_x_id = 2, local_size_y_id = 3, local_size_z = 1) in;
+
+layout(push_constant) uniform PushConstants {
+    ivec2 direction;
+    int radius;
+    float intensity;
+} push_constants;
+
+layout(set = 0, binding = 0, rgba16f) restrict uniform readonly image2D src_img;
+layout(set = 0, binding = 1, rgba16f) restrict uniform writeonly image2D dst_img;
+
+void blur(ivec2 pos) {
+    float sigma = max(float(push_constants.radius) / 3.0, 1.0);
+    vec3 sum = vec3(0.0);
+    float weight_sum = 0.0;
+    for (int i = -push_constants.radius; i <= push_constants.radius; i++) {
+        ivec2 sample_pos = pos + push_constants.direction * i;
+        float weight = exp(-float(i * i) / (2.0 * sigma * sigma));
+        weight_sum += weight;
+        if (sample_pos.x >= 0 && sample_pos.x < canvas_size_x &&
+        sample_pos.y >= 0 && sample_pos.y < canvas_size_y) {
+            sum += imageLoad(src_img, sample_pos).rgb * weight;
+        }
+    }
+    imageStore(dst_img, pos, vec4(sum / weight_sum * push_constants.intensity, 1.0));
+}
+
+void main() {
+    blur(ivec2(gl_GlobalInvocationID.xy));
+}
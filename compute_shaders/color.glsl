@@ -1,33 +1,117 @@
 #version 450
 
 #include "includes.glsl"
+#include "color_utils.glsl"
 
-// Transform a uint color to vec4 (r, g, b, a)
-vec4 matter_color_to_vec4(uint color) {
-    return  vec4(float((color >> uint(16)) & uint(255)) / 255.0,
-                float((color >> uint(8)) & uint(255)) / 255.0,
-                float(color & uint(255)) / 255.0,
-                1.0);
+// Must match ViewMode in particle_simulator.rs
+#define VIEW_MODE_NORMAL 0
+#define VIEW_MODE_MATTER_ID 1
+#define VIEW_MODE_MOVED 2
+#define VIEW_MODE_TILES 3
+#define VIEW_MODE_RAW_COLOR 4
+
+// Background walls are darkened so that matter in front of them stands out
+#define BACKGROUND_SHADE 0.6
+
+vec3 hsv_to_rgb(vec3 hsv) {
+    vec3 rgb = clamp(abs(mod(hsv.x * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+    return hsv.z * mix(vec3(1.0), rgb, hsv.y);
+}
+
+// Color of a cell, showing the background through empty cells
+vec4 cell_color(ivec2 pos, Matter matter) {
+    uint background_value = read_background(pos);
+    if (is_empty(matter) && has_background(background_value)) {
+        vec4 color = matter_color_to_vec4(background_value >> uint(8));
+        return vec4(color.rgb * BACKGROUND_SHADE, 1.0);
+    }
+    return matter_color_to_vec4(matter.color);
+}
+
+// Whether a cell is on the edge of the outlined rectangle
+bool is_on_selection_outline(ivec2 pos) {
+    ivec2 selection_min = ivec2(push_constants.selection_min_x, push_constants.selection_min_y);
+    ivec2 selection_max = ivec2(push_constants.selection_max_x, push_constants.selection_max_y);
+    bool inside = all(greaterThanEqual(pos, selection_min)) && all(lessThanEqual(pos, selection_max));
+    bool on_edge = any(equal(pos, selection_min)) || any(equal(pos, selection_max));
+    return inside && on_edge;
+}
+
+// Distinct color for each matter id so that similar looking matter can be told apart
+vec4 matter_id_false_color(Matter matter) {
+    if (is_empty(matter)) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    // Golden ratio spreads consecutive ids around the hue circle
+    return vec4(hsv_to_rgb(vec3(fract(float(matter.matter) * 0.618034), 0.8, 1.0)), 1.0);
+}
+
+// Cells whose value changed since the start of the step are highlighted, others are dimmed
+vec4 moved_color(ivec2 pos, Matter matter) {
+    if (matter_in[get_index(pos)] != matter_step_start[get_index(pos)]) {
+        return vec4(1.0, 0.0, 0.0, 1.0);
+    }
+    return vec4(matter_color_to_vec4(matter.color).rgb * 0.3, 1.0);
+}
+
+// Draw the edges of each workgroup over the matter colors
+vec4 tiles_color(ivec2 pos, Matter matter) {
+    vec4 color = cell_color(pos, matter);
+    if (gl_LocalInvocationID.x == 0 || gl_LocalInvocationID.y == 0) {
+        return vec4(mix(color.rgb, vec3(0.0, 1.0, 0.0), 0.5), 1.0);
+    }
+    return color;
+}
+
+vec4 view_mode_color(ivec2 pos, Matter matter) {
+    switch (int(push_constants.view_mode)) {
+        case VIEW_MODE_MATTER_ID:
+            return matter_id_false_color(matter);
+        case VIEW_MODE_MOVED:
+            return moved_color(pos, matter);
+        case VIEW_MODE_TILES:
+            return tiles_color(pos, matter);
+        default:
+            return cell_color(pos, matter);
+    }
 }
 
-// 0-1 linear  from  0-255 sRGB
-vec3 linear_from_srgb(vec3 srgb) {
-    bvec3 cutoff = lessThan(srgb, vec3(10.31475));
-    vec3 lower = srgb / vec3(3294.6);
-    vec3 higher = pow((srgb + vec3(14.025)) / vec3(269.025), vec3(2.4));
-    return mix(higher, lower, cutoff);
+// Emissive matter is written to the emission image in linear space, everything else is black
+void write_emission_to_image(ivec2 pos, Matter matter) {
+    vec4 emission = vec4(0.0, 0.0, 0.0, 1.0);
+    if (is_emissive(matter)) {
+        emission = linear_from_srgba(matter_color_to_vec4(matter.color));
+    }
+    write_emission_color(pos, emission);
 }
 
-vec4 linear_from_srgba(vec4 srgba) {
-    return vec4(linear_from_srgb(srgba.rgb * 255.0), srgba.a);
+// Light reaching a cell including ambient light and the cell's own emission
+float light_at(ivec2 pos, Matter matter) {
+    vec2 light = light_in[get_index(pos)];
+    float emission = get_properties(matter).emission;
+    return min(push_constants.ambient_light + light.x + light.y + emission, 1.0);
 }
 
 void write_color_to_image(ivec2 pos) {
     Matter matter = read_matter(pos);
+    write_emission_to_image(pos, matter);
+    // Raw color bits are written as is without color space conversion
+    if (push_constants.view_mode == VIEW_MODE_RAW_COLOR) {
+        write_image_color(pos, matter_color_to_vec4(matter.color));
+        return;
+    }
     // Our swapchain is in SRGB color space (default by bevy_vulkano). The system tries to interpret our canvas image as such. But our canvas image is
     // UNORM (only way to ImageStore), thus we need to convert the colors to linear space. We are assuming that images
     // Are already in SRGB color space. When we render, the linear gets interpreted as SRGB.
-    write_image_color(pos, linear_from_srgba(matter_color_to_vec4(matter.color)));
+    vec4 color = linear_from_srgba(view_mode_color(pos, matter));
+    // Lighting is applied in linear space and only to the normal view
+    if (push_constants.view_mode == VIEW_MODE_NORMAL) {
+        color.rgb *= light_at(pos, matter);
+    }
+    if (is_on_selection_outline(pos)) {
+        color.rgb = mix(color.rgb, vec3(1.0), 0.7);
+    }
+    write_image_color(pos, color);
 }
 
 void main() {
@@ -0,0 +1,21 @@
+// Color conversions shared by passes writing the canvas image
+
+// Transform a uint color to vec4 (r, g, b, a)
+vec4 matter_color_to_vec4(uint color) {
+    return  vec4(float((color >> uint(16)) & uint(255)) / 255.0,
+                float((color >> uint(8)) & uint(255)) / 255.0,
+                float(color & uint(255)) / 255.0,
+                1.0);
+}
+
+// 0-1 linear  from  0-255 sRGB
+vec3 linear_from_srgb(vec3 srgb) {
+    bvec3 cutoff = lessThan(srgb, vec3(10.31475));
+    vec3 lower = srgb / vec3(3294.6);
+    vec3 higher = pow((srgb + vec3(14.025)) / vec3(269.025), vec3(2.4));
+    return mix(higher, lower, cutoff);
+}
+
+vec4 linear_from_srgba(vec4 srgba) {
+    return vec4(linear_from_srgb(srgba.rgb * 255.0), srgba.a);
+}
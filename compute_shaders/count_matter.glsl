@@ -0,0 +1,37 @@
+#version 450
+
+#include "includes.glsl"
+
+// Histogram of the workgroup, added to the global counts once all cells of the workgroup are counted
+shared uint local_counts[MATTER_ID_COUNT];
+
+void count_matter(ivec2 pos) {
+    uint local_index = gl_LocalInvocationIndex;
+    uint local_size = gl_WorkGroupSize.x * gl_WorkGroupSize.y;
+    for (uint i = local_index; i < MATTER_ID_COUNT; i += local_size) {
+        local_counts[i] = 0;
+    }
+    barrier();
+
+    atomicAdd(local_counts[read_matter(pos).matter], uint(1));
+    // Matter in flight is counted too, so that ejecting and landing conserve matter. Canvases
+    // with fewer cells than particles count several particles per cell.
+    uint cell_count = uint(canvas_size_x * canvas_size_y);
+    for (uint i = uint(get_index(pos)); i < get_particle_count(); i += cell_count) {
+        Matter particle_matter = new_matter(particles_in[i].matter);
+        if (!is_empty(particle_matter)) {
+            atomicAdd(local_counts[particle_matter.matter], uint(1));
+        }
+    }
+    barrier();
+
+    for (uint i = local_index; i < MATTER_ID_COUNT; i += local_size) {
+        if (local_counts[i] > 0) {
+            atomicAdd(matter_counts[push_constants.count_slot * MATTER_ID_COUNT + i], local_counts[i]);
+        }
+    }
+}
+
+void main() {
+    count_matter(get_current_sim_pos());
+}
@@ -0,0 +1,37 @@
+#version 450
+
+#include "includes.glsl"
+
+// Matter with gravity inside the ejection circle leaves the grid as particles flying away
+// from the circle's center. Particles are appended to particles_in.
+void eject(ivec2 pos) {
+    ivec2 from_center = pos - ivec2(push_constants.eject_x, push_constants.eject_y);
+    int radius = push_constants.eject_radius;
+    if (from_center.x * from_center.x + from_center.y * from_center.y > radius * radius) {
+        return;
+    }
+    Matter matter = read_matter(pos);
+    if (!has_gravity(matter)) {
+        return;
+    }
+    uint slot = atomicAdd(particle_count_in, uint(1));
+    if (slot >= uint(MAX_PARTICLES)) {
+        return;
+    }
+    vec2 outward = from_center == ivec2(0) ? vec2(0.0, 1.0) : normalize(vec2(from_center));
+    // Some randomness so that ejected matter spreads out
+    float speed = push_constants.eject_burst * (0.5 + random(pos, uint(0)));
+    Particle particle;
+    particle.pos = vec2(pos) + vec2(0.5);
+    particle.velocity = vec2(push_constants.eject_velocity_x, push_constants.eject_velocity_y) + outward * speed;
+    particle.matter = matter_to_uint(matter);
+    particle.padding = 0;
+    particles_in[slot] = particle;
+    // Each invocation only writes its own cell, so the grid can be written in place
+    matter_in[get_index(pos)] = matter_to_uint(new_matter(empty_matter));
+    velocity_in[get_index(pos)] = 0.0;
+}
+
+void main() {
+    eject(get_current_sim_pos());
+}
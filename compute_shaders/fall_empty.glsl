@@ -2,16 +2,70 @@
 
 #include "includes.glsl"
 
+// Velocity gained per fall pass
+#define GRAVITY 0.2
+// Maximum cells moved per fall pass. Also how far up empty cells look for falling matter.
+#define MAX_FALL_DISTANCE 8
 
+// Whether falling matter can move into a position. Matter falling into void is deleted.
+bool is_free(ivec2 pos) {
+    if (!is_inside_sim_canvas(pos) && get_boundary(pos) == BOUNDARY_WALL) {
+        return false;
+    }
+    return is_empty(get_matter_at(pos));
+}
+
+// Velocity of falling matter after this pass' acceleration
+float fall_velocity(Matter matter) {
+    return min(matter.velocity + GRAVITY, float(MAX_FALL_DISTANCE));
+}
+
+// How many cells matter at pos falls this pass. The path is walked down to the
+// first obstacle, so matter never jumps over anything.
+int fall_distance(ivec2 pos, Matter matter) {
+    if (!has_gravity(matter)) {
+        return 0;
+    }
+    int steps = clamp(int(fall_velocity(matter)), 1, MAX_FALL_DISTANCE);
+    int distance = 0;
+    while (distance < steps && is_free(pos - ivec2(0, distance + 1))) {
+        distance++;
+    }
+    return distance;
+}
+
+// Matter keeps accelerating while its path is free and stops when it hits something
+Matter fallen_matter(ivec2 pos, Matter matter) {
+    int steps = clamp(int(fall_velocity(matter)), 1, MAX_FALL_DISTANCE);
+    matter.velocity = fall_distance(pos, matter) == steps ? fall_velocity(matter) : 0.0;
+    return matter;
+}
+
+// Each cell pulls in the nearest matter above it if that matter lands exactly here,
+// and matter that falls away leaves an empty cell. Since the landing position is computed
+// the same way by both cells, every matter ends up in exactly one cell.
 void fall_empty(ivec2 pos) {
     Matter current = read_matter(pos);
-    Matter up = get_neighbor(pos, UP);
-    Matter down = get_neighbor(pos, DOWN);
     Matter m = current;
-    if (!is_at_border_top(pos) && falls_on_empty(up, current)) {
-        m = up;
-    } else if (!is_at_border_bottom(pos) && falls_on_empty(current, down)) {
-        m = down;
+    if (is_empty(current)) {
+        for (int d = 1; d <= MAX_FALL_DISTANCE; d++) {
+            ivec2 above_pos = pos + ivec2(0, d);
+            if (!is_inside_sim_canvas(above_pos) && get_boundary(above_pos) != BOUNDARY_WRAP) {
+                break;
+            }
+            Matter above = get_matter_at(above_pos);
+            if (!is_empty(above)) {
+                if (fall_distance(above_pos, above) == d) {
+                    m = fallen_matter(above_pos, above);
+                }
+                break;
+            }
+        }
+    } else if (fall_distance(pos, current) > 0) {
+        m = new_matter(empty_matter);
+    } else {
+        // Landed or not falling
+        m.velocity = 0.0;
     }
     write_matter(pos, m);
 }
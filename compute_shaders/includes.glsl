@@ -9,14 +9,108 @@ layout(local_size_x_id = 3, local_size_y_id = 4, local_size_z = 1) in;
 layout(push_constant) uniform PushConstants {
     uint sim_step;
     uint move_step;
+    uint count_slot;
+    uint view_mode;
+    float sky_light;
+    float ambient_light;
+    uint boundary_top;
+    uint boundary_bottom;
+    uint boundary_left;
+    uint boundary_right;
+    uint world_object_count;
+    // Ejection of cells into particles, see eject.glsl
+    int eject_x;
+    int eject_y;
+    int eject_radius;
+    float eject_burst;
+    float eject_velocity_x;
+    float eject_velocity_y;
+    uint reaction_count;
+    float background_opacity;
+    // Rectangle outlined by the color pass, empty when min > max
+    int selection_min_x;
+    int selection_min_y;
+    int selection_max_x;
+    int selection_max_y;
+    // Salt of random numbers, set from the command line
+    uint seed;
+    uint sensor_count;
+    uint paint_count;
 } push_constants;
 
+// Boundary modes of canvas edges. Must match BoundaryMode in particle_simulator.rs
+#define BOUNDARY_WALL 0
+#define BOUNDARY_VOID 1
+#define BOUNDARY_WRAP 2
+
+#include "matter.glsl"
+
 //Buffers
 layout(set = 0, binding = 0) restrict buffer MatterInBuffer { uint matter_in[]; };
 layout(set = 0, binding = 1) restrict writeonly buffer MatterOutBuffer { uint matter_out[]; };
 layout(set = 0, binding = 2, rgba8) restrict uniform writeonly image2D canvas_img;
-
-#include "matter.glsl"
+layout(set = 0, binding = 3) restrict buffer MatterCountsBuffer { uint matter_counts[]; };
+// Matter at the start of the step, only filled for the moved cells view
+layout(set = 0, binding = 4) restrict readonly buffer MatterStepStartBuffer { uint matter_step_start[]; };
+layout(set = 0, binding = 5) restrict readonly buffer MatterPropertiesBuffer { MatterProperties matter_properties[]; };
+// Color of emissive matter, input of the glow post-processing
+layout(set = 0, binding = 6, rgba16f) restrict uniform writeonly image2D emission_img;
+// Light arriving at each cell as (sky, emitted), ping-ponged by the light passes
+layout(set = 0, binding = 7) restrict readonly buffer LightInBuffer { vec2 light_in[]; };
+layout(set = 0, binding = 8) restrict writeonly buffer LightOutBuffer { vec2 light_out[]; };
+// Emitters and drains, see world_objects.rs
+struct WorldObject {
+    uint kind;
+    uint shape;
+    uint matter;
+    float probability;
+    ivec2 min;
+    ivec2 max;
+};
+layout(set = 0, binding = 9) restrict readonly buffer WorldObjectBuffer { WorldObject world_objects[]; };
+// Velocity of each cell, swapped together with matter
+layout(set = 0, binding = 10) restrict buffer VelocityInBuffer { float velocity_in[]; };
+layout(set = 0, binding = 11) restrict writeonly buffer VelocityOutBuffer { float velocity_out[]; };
+// Matter flying freely outside the grid, see particles.rs
+#define MAX_PARTICLES 65536
+struct Particle {
+    vec2 pos;
+    vec2 velocity;
+    uint matter;
+    uint padding;
+};
+layout(set = 0, binding = 12) restrict buffer ParticlesInBuffer { Particle particles_in[]; };
+layout(set = 0, binding = 13) restrict writeonly buffer ParticlesOutBuffer { Particle particles_out[]; };
+// Number of particles, may exceed MAX_PARTICLES when appends overflow
+layout(set = 0, binding = 14) restrict buffer ParticleCountInBuffer { uint particle_count_in; };
+layout(set = 0, binding = 15) restrict buffer ParticleCountOutBuffer { uint particle_count_out; };
+// Reactions between neighboring matter, see reactions.rs
+struct Reaction {
+    uint a;
+    uint b;
+    uint a_out;
+    uint b_out;
+    float probability;
+};
+layout(set = 0, binding = 16) restrict readonly buffer ReactionBuffer { Reaction reactions[]; };
+// Background walls behind empty cells packed like matter, see background.rs. Only written by
+// the paint pass.
+layout(set = 0, binding = 17) restrict buffer BackgroundBuffer { uint background[]; };
+// Rectangles whose matter is counted, see sensors.rs
+struct Sensor {
+    ivec2 min;
+    ivec2 max;
+};
+layout(set = 0, binding = 18) restrict readonly buffer SensorBuffer { Sensor sensors[]; };
+// Count of each matter id inside each sensor, MATTER_ID_COUNT per sensor
+layout(set = 0, binding = 19) restrict buffer SensorCountsBuffer { uint sensor_counts[]; };
+// Cell writes of the painting tools, at most one per cell and target, see paint.glsl
+struct Paint {
+    uint index;
+    uint value;
+    uint flags;
+};
+layout(set = 0, binding = 20) restrict readonly buffer PaintBuffer { Paint paints[]; };
 
 //general utility functions
 
@@ -34,7 +128,18 @@ bool is_inside_sim_canvas(ivec2 pos) {
 }
 
 Matter read_matter(ivec2 pos) {
-    return new_matter(matter_in[get_index(pos)]);
+    Matter matter = new_matter(matter_in[get_index(pos)]);
+    matter.velocity = velocity_in[get_index(pos)];
+    return matter;
+}
+
+// Background of a cell, id 0 is no background
+uint read_background(ivec2 pos) {
+    return background[get_index(pos)];
+}
+
+bool has_background(uint background_value) {
+    return (background_value & uint(255)) != 0;
 }
 
 uint matter_to_uint(Matter matter) {
@@ -43,12 +148,45 @@ uint matter_to_uint(Matter matter) {
 
 void write_matter(ivec2 pos, Matter matter) {
     matter_out[get_index(pos)] = matter_to_uint(matter);
+    velocity_out[get_index(pos)] = matter.velocity;
+}
+
+// Particles are indexed by the linear index of the invocation in particle_work_groups, which
+// doesn't depend on the canvas size
+uint get_particle_index() {
+    uint width = gl_NumWorkGroups.x * gl_WorkGroupSize.x;
+    return gl_GlobalInvocationID.y * width + gl_GlobalInvocationID.x;
+}
+
+uint get_particle_count() {
+    return min(particle_count_in, uint(MAX_PARTICLES));
+}
+
+// PCG hash
+uint hash(uint x) {
+    uint state = x * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> uint(28)) + uint(4))) ^ state) * 277803737u;
+    return (word >> uint(22)) ^ word;
+}
+
+// Random number in [0, 1) for a cell and salt, different on every step
+float random(ivec2 pos, uint salt) {
+    uint state = hash(push_constants.sim_step ^ hash(salt ^ hash(push_constants.seed)));
+    return float(hash(uint(get_index(pos)) ^ state)) / 4294967296.0;
 }
 
 void write_image_color(ivec2 pos, vec4 color) {
     imageStore(canvas_img, pos, color);
 }
 
+void write_emission_color(ivec2 pos, vec4 color) {
+    imageStore(emission_img, pos, color);
+}
+
+MatterProperties get_properties(Matter matter) {
+    return matter_properties[matter.matter];
+}
+
 // utility functions falling sand
 
 #include "dirs.glsl"
@@ -69,20 +207,65 @@ bool is_at_border_left(ivec2 pos) {
     return pos.x == 0;
 }
 
+// Matter can't move across wall edges. Void and wrapping edges are handled in get_neighbor.
+bool is_blocked_top(ivec2 pos) {
+    return is_at_border_top(pos) && push_constants.boundary_top == BOUNDARY_WALL;
+}
+
+bool is_blocked_bottom(ivec2 pos) {
+    return is_at_border_bottom(pos) && push_constants.boundary_bottom == BOUNDARY_WALL;
+}
+
+bool is_blocked_right(ivec2 pos) {
+    return is_at_border_right(pos) && push_constants.boundary_right == BOUNDARY_WALL;
+}
+
+bool is_blocked_left(ivec2 pos) {
+    return is_at_border_left(pos) && push_constants.boundary_left == BOUNDARY_WALL;
+}
+
+// Boundary mode beyond a position outside the canvas. In corners wall wins over void and
+// void over wrap.
+uint get_boundary(ivec2 pos) {
+    uint x_mode = BOUNDARY_WRAP;
+    uint y_mode = BOUNDARY_WRAP;
+    if (pos.x < 0) {
+        x_mode = push_constants.boundary_left;
+    } else if (pos.x >= canvas_size_x) {
+        x_mode = push_constants.boundary_right;
+    }
+    if (pos.y < 0) {
+        y_mode = push_constants.boundary_bottom;
+    } else if (pos.y >= canvas_size_y) {
+        y_mode = push_constants.boundary_top;
+    }
+    return min(x_mode, y_mode);
+}
+
+ivec2 wrap_pos(ivec2 pos) {
+    return ivec2((pos.x + canvas_size_x) % canvas_size_x, (pos.y + canvas_size_y) % canvas_size_y);
+}
+
 ivec2 get_pos_at_dir(ivec2 pos, int dir) {
     return pos + OFFSETS[dir];
 }
 
+// Matter at any position, outside the canvas according to boundary modes
+Matter get_matter_at(ivec2 pos) {
+    if (is_inside_sim_canvas(pos)) {
+        return read_matter(pos);
+    } else if (get_boundary(pos) == BOUNDARY_WRAP) {
+        return read_matter(wrap_pos(pos));
+    } else {
+        return new_matter(empty_matter);
+    }
+}
+
 // | 0 1 2 |
 // | 7 x 3 |
 // | 6 5 4 |
 Matter get_neighbor(ivec2 pos, int dir) {
-    ivec2 neighbor_pos = get_pos_at_dir(pos, dir);
-    if (is_inside_sim_canvas(neighbor_pos)) {
-        return read_matter(neighbor_pos);
-    } else {
-        return new_matter(empty_matter);
-    }
+    return get_matter_at(get_pos_at_dir(pos, dir));
 }
 
 //shortcut for water (not added)
@@ -94,9 +277,12 @@ bool is_empty(Matter matter) {
     return matter.matter == 0;
 }
 
-//anything that is above a certain number has gravity
+bool is_emissive(Matter matter) {
+    return (get_properties(matter).flags & MATTER_FLAG_EMISSIVE) != 0;
+}
+
 bool has_gravity(Matter m) {
-    return m.matter > 1;
+    return (get_properties(m).flags & MATTER_FLAG_GRAVITY) != 0;
 }
 
 bool falls_on_empty(Matter from, Matter to) {
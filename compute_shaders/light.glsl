@@ -0,0 +1,42 @@
+#version 450
+
+#include "includes.glsl"
+
+// Fraction of light kept when moving to a neighboring cell
+#define LIGHT_FALLOFF 0.96
+
+// Light leaving a cell towards its neighbors. Opaque cells are lit themselves but block
+// the light passing through them. Background walls block light on top of the matter.
+vec2 outgoing_light(ivec2 pos) {
+    MatterProperties properties = get_properties(read_matter(pos));
+    vec2 light = light_in[get_index(pos)] * (1.0 - properties.opacity);
+    if (has_background(read_background(pos))) {
+        light *= 1.0 - push_constants.background_opacity;
+    }
+    light.y = max(light.y, properties.emission);
+    return light;
+}
+
+// Sky light comes from above the top edge, other edges are dark
+vec2 neighbor_light(ivec2 pos, int dir) {
+    ivec2 neighbor_pos = get_pos_at_dir(pos, dir);
+    if (is_inside_sim_canvas(neighbor_pos)) {
+        return outgoing_light(neighbor_pos);
+    } else if (neighbor_pos.y >= canvas_size_y) {
+        return vec2(push_constants.sky_light, 0.0);
+    }
+    return vec2(0.0);
+}
+
+// Sky light falls straight down without falloff, all light spreads to every neighbor with falloff
+void propagate_light(ivec2 pos) {
+    vec2 light = vec2(neighbor_light(pos, UP).x, 0.0);
+    for (int dir = 0; dir < 8; dir++) {
+        light = max(light, neighbor_light(pos, dir) * LIGHT_FALLOFF);
+    }
+    light_out[get_index(pos)] = light;
+}
+
+void main() {
+    propagate_light(get_current_sim_pos());
+}
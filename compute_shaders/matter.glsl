@@ -1,11 +1,27 @@
+// Matter ids are stored in the lowest byte
+#define MATTER_ID_COUNT 256
+
 struct Matter {
     uint matter;
     uint color;
+    // Falling speed in cells per fall pass, stored in its own buffer
+    float velocity;
 };
 
 Matter new_matter(uint matter) {
     Matter m;
     m.matter = (matter & uint(255));
     m.color = matter >> uint(8);
+    m.velocity = 0.0;
     return m;
 }
+
+#define MATTER_FLAG_EMISSIVE 1
+#define MATTER_FLAG_GRAVITY 2
+
+// Must match MatterProperties in matter.rs
+struct MatterProperties {
+    uint flags;
+    float opacity;
+    float emission;
+};
@@ -0,0 +1,28 @@
+#version 450
+
+#include "includes.glsl"
+
+// Must match paint flags in particle_simulator.rs
+#define PAINT_BACKGROUND 1
+#define PAINT_ONLY_EMPTY 2
+
+// Write a painted cell. Painted matter doesn't keep the velocity of what it replaced.
+void apply_paint(Paint paint) {
+    if ((paint.flags & PAINT_BACKGROUND) != 0) {
+        background[paint.index] = paint.value;
+        return;
+    }
+    if ((paint.flags & PAINT_ONLY_EMPTY) != 0 && !is_empty(new_matter(matter_in[paint.index]))) {
+        return;
+    }
+    matter_in[paint.index] = paint.value;
+    velocity_in[paint.index] = 0.0;
+}
+
+void main() {
+    // Paints are spread over invocations, there may be more of them than cells
+    uint cell_count = uint(canvas_size_x * canvas_size_y);
+    for (uint i = uint(get_index(get_current_sim_pos())); i < push_constants.paint_count; i += cell_count) {
+        apply_paint(paints[i]);
+    }
+}
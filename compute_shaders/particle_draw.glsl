@@ -0,0 +1,21 @@
+#version 450
+
+#include "includes.glsl"
+#include "color_utils.glsl"
+
+// Draw each particle as a point on the canvas image, after the color pass
+void draw_particle(uint index) {
+    if (index >= get_particle_count()) {
+        return;
+    }
+    Particle particle = particles_in[index];
+    Matter matter = new_matter(particle.matter);
+    if (is_empty(matter)) {
+        return;
+    }
+    write_image_color(ivec2(floor(particle.pos)), linear_from_srgba(matter_color_to_vec4(matter.color)));
+}
+
+void main() {
+    draw_particle(get_particle_index());
+}
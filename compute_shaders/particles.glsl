@@ -0,0 +1,74 @@
+#version 450
+
+#include "includes.glsl"
+
+// Velocity lost to gravity per step in cells
+#define PARTICLE_GRAVITY 0.2
+
+// Put a particle into its cell if the cell is still empty. Other particles may land at the
+// same time, so the cell is claimed atomically.
+bool land(Particle particle) {
+    int index = get_index(ivec2(floor(particle.pos)));
+    uint current = matter_in[index];
+    if (!is_empty(new_matter(current))) {
+        return false;
+    }
+    if (atomicCompSwap(matter_in[index], current, particle.matter) != current) {
+        return false;
+    }
+    velocity_in[index] = max(-particle.velocity.y, 0.0);
+    return true;
+}
+
+// Move a particle along its velocity one cell at a time until it hits matter or a wall,
+// where it lands. Surviving particles are appended to particles_out.
+void update_particle(uint index) {
+    if (index >= get_particle_count()) {
+        return;
+    }
+    Particle particle = particles_in[index];
+    if (is_empty(new_matter(particle.matter))) {
+        return;
+    }
+    particle.velocity.y -= PARTICLE_GRAVITY;
+    int substeps = max(int(ceil(max(abs(particle.velocity.x), abs(particle.velocity.y)))), 1);
+    vec2 delta = particle.velocity / float(substeps);
+    bool hit = false;
+    for (int i = 0; i < substeps && !hit; i++) {
+        vec2 next_pos = particle.pos + delta;
+        ivec2 next_cell = ivec2(floor(next_pos));
+        if (!is_inside_sim_canvas(next_cell)) {
+            uint boundary = get_boundary(next_cell);
+            if (boundary == BOUNDARY_VOID) {
+                // Deleted like cells moving into void
+                return;
+            } else if (boundary == BOUNDARY_WRAP) {
+                next_pos = vec2(wrap_pos(next_cell)) + fract(next_pos);
+                next_cell = wrap_pos(next_cell);
+            } else {
+                hit = true;
+                continue;
+            }
+        }
+        if (!is_empty(read_matter(next_cell))) {
+            hit = true;
+        } else {
+            particle.pos = next_pos;
+        }
+    }
+    if (hit) {
+        if (land(particle)) {
+            return;
+        }
+        // Cell was taken, try again a cell higher on the next step
+        particle.velocity = vec2(0.0);
+        if (particle.pos.y + 1.0 < float(canvas_size_y)) {
+            particle.pos.y += 1.0;
+        }
+    }
+    particles_out[atomicAdd(particle_count_out, uint(1))] = particle;
+}
+
+void main() {
+    update_particle(get_particle_index());
+}
@@ -0,0 +1,58 @@
+#version 450
+
+#include "includes.glsl"
+
+// Direction of the pairs of each step, see get_partner
+const int PAIRING_DIRS[4] = int[4](RIGHT, UP, UP_RIGHT, UP_LEFT);
+
+// Each cell is paired with exactly one neighbor per step, so that both cells of a pair agree
+// on whether they react without writing each other. All pairs of a step lie along one of four
+// directions, and cells on even columns (even rows for vertical pairs) lead, pairing forwards.
+// Leading flips to odd columns every other round, so over 8 steps every cell meets all of its
+// 8 neighbors.
+ivec2 get_partner(ivec2 pos) {
+    uint pairing = push_constants.sim_step % uint(4);
+    int shift = int((push_constants.sim_step / uint(4)) % uint(2));
+    ivec2 dir = OFFSETS[PAIRING_DIRS[pairing]];
+    int lead = dir.x != 0 ? pos.x : pos.y;
+    return (lead + shift) % 2 == 0 ? pos + dir : pos - dir;
+}
+
+// First reaction of the table matching the pair. Both cells of a pair order it the same way,
+// so they find the same reaction and roll the same random number.
+void react(ivec2 pos) {
+    Matter current = read_matter(pos);
+    Matter m = current;
+    ivec2 partner_pos = get_partner(pos);
+    if (is_inside_sim_canvas(partner_pos)) {
+        Matter partner = read_matter(partner_pos);
+        bool is_first = get_index(pos) < get_index(partner_pos);
+        ivec2 first_pos = is_first ? pos : partner_pos;
+        uint first = is_first ? current.matter : partner.matter;
+        uint second = is_first ? partner.matter : current.matter;
+        for (uint i = 0; i < push_constants.reaction_count; i++) {
+            Reaction reaction = reactions[i];
+            // Outputs of the first and second cell
+            uint first_out;
+            uint second_out;
+            if (reaction.a == first && reaction.b == second) {
+                first_out = reaction.a_out;
+                second_out = reaction.b_out;
+            } else if (reaction.a == second && reaction.b == first) {
+                first_out = reaction.b_out;
+                second_out = reaction.a_out;
+            } else {
+                continue;
+            }
+            if (random(first_pos, i) < reaction.probability) {
+                m = new_matter(is_first ? first_out : second_out);
+            }
+            break;
+        }
+    }
+    write_matter(pos, m);
+}
+
+void main() {
+    react(get_current_sim_pos());
+}
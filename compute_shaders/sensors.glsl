@@ -0,0 +1,21 @@
+#version 450
+
+#include "includes.glsl"
+
+// Add the matter of a cell to the counts of every sensor containing it. Empty cells aren't counted.
+void count_sensors(ivec2 pos) {
+    Matter m = read_matter(pos);
+    if (is_empty(m)) {
+        return;
+    }
+    for (uint i = 0; i < push_constants.sensor_count; i++) {
+        Sensor sensor = sensors[i];
+        if (all(greaterThanEqual(pos, sensor.min)) && all(lessThanEqual(pos, sensor.max))) {
+            atomicAdd(sensor_counts[i * MATTER_ID_COUNT + m.matter], uint(1));
+        }
+    }
+}
+
+void main() {
+    count_sensors(get_current_sim_pos());
+}
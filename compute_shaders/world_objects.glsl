@@ -0,0 +1,38 @@
+#version 450
+
+#include "includes.glsl"
+
+// Must match world object constants in world_objects.rs
+#define WORLD_OBJECT_EMITTER 0
+#define WORLD_OBJECT_DRAIN 1
+#define SHAPE_CIRCLE 0
+#define SHAPE_RECT 1
+
+bool is_inside_shape(ivec2 pos, WorldObject object) {
+    if (object.shape == SHAPE_CIRCLE) {
+        ivec2 d = pos - object.min;
+        return d.x * d.x + d.y * d.y <= object.max.x * object.max.x;
+    }
+    return all(greaterThanEqual(pos, object.min)) && all(lessThanEqual(pos, object.max));
+}
+
+// Drains empty their cells, emitters fill empty cells by chance
+void apply_world_objects(ivec2 pos) {
+    Matter m = read_matter(pos);
+    for (uint i = 0; i < push_constants.world_object_count; i++) {
+        WorldObject object = world_objects[i];
+        if (!is_inside_shape(pos, object)) {
+            continue;
+        }
+        if (object.kind == WORLD_OBJECT_DRAIN) {
+            m = new_matter(empty_matter);
+        } else if (is_empty(m) && random(pos, i) < object.probability) {
+            m = new_matter(object.matter);
+        }
+    }
+    write_matter(pos, m);
+}
+
+void main() {
+    apply_world_objects(get_current_sim_pos());
+}
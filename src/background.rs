@@ -0,0 +1,48 @@
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::utils::{u32_rgba_to_u8_rgba, u8_rgba_to_u32_rgba, EMPTY_COLOR};
+
+// Walls drawn behind empty cells. They aren't simulated and matter moves in front of them.
+#[repr(u8)]
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BackgroundId {
+    Empty = 0,
+    Stone = 1,
+    Brick = 2,
+    Wood = 3,
+}
+
+impl Default for BackgroundId {
+    fn default() -> Self {
+        BackgroundId::Empty
+    }
+}
+
+impl BackgroundId {
+    // Background with given id, None if the id is unknown
+    pub fn from_id(id: u8) -> Option<BackgroundId> {
+        BackgroundId::iter().find(|&background| background as u8 == id)
+    }
+
+    // Background with given name as printed with Debug, e.g. "Brick"
+    pub fn from_name(name: &str) -> Option<BackgroundId> {
+        BackgroundId::iter().find(|background| format!("{:?}", background) == name)
+    }
+
+    fn color_rgba_u8(&self) -> [u8; 4] {
+        let color = match *self {
+            BackgroundId::Empty => EMPTY_COLOR,
+            BackgroundId::Stone => 0x4a4a4aff,
+            BackgroundId::Brick => 0x6e3b2aff,
+            BackgroundId::Wood => 0x5c4326ff,
+        };
+        u32_rgba_to_u8_rgba(color)
+    }
+
+    // Background cell value, packed like MatterWithColor
+    pub fn value(&self) -> u32 {
+        let color = self.color_rgba_u8();
+        u8_rgba_to_u32_rgba(color[0], color[1], color[2], *self as u8)
+    }
+}
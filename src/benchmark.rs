@@ -0,0 +1,284 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+use bevy::math::{IVec2, UVec2};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use vulkano_util::context::VulkanoContext;
+
+use particle_simulation::{
+    background::BackgroundId,
+    matter::{MatterId, MatterWithColor},
+    particle_simulator::CASimulator,
+    reactions::reload_reactions,
+    stamps::PasteMode,
+    Config,
+};
+
+// Canvas sizes every scene is simulated at
+const CANVAS_SIZES: [u32; 3] = [256, 512, 1024];
+// Steps run before measuring, so that the first submissions don't count
+const WARMUP_STEPS: u32 = 10;
+const MEASURED_STEPS: u32 = 200;
+// Brush radii of the draw benchmarks. Cpu paths run on the largest canvas.
+const BRUSH_RADII: [f32; 3] = [16.0, 64.0, 256.0];
+// Points per brush stroke and strokes per draw benchmark
+const STROKE_LENGTH: i32 = 64;
+const STROKES: u32 = 20;
+// Repetitions of the grid copy benchmarks
+const GRID_COPIES: u32 = 20;
+
+// Standard scenes the simulation is measured with
+#[derive(EnumIter, Debug, Copy, Clone)]
+enum BenchmarkScene {
+    Empty,
+    // Sand in the upper half, falling during the run
+    HalfSand,
+    FullWater,
+    // Random material in every cell, so everything moves and reacts
+    NoisyMix,
+}
+
+// Integer hash for reproducible noise
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+impl BenchmarkScene {
+    fn label(&self) -> &'static str {
+        match *self {
+            BenchmarkScene::Empty => "step_empty",
+            BenchmarkScene::HalfSand => "step_half_sand",
+            BenchmarkScene::FullWater => "step_full_water",
+            BenchmarkScene::NoisyMix => "step_noisy_mix",
+        }
+    }
+
+    fn matter_grid(&self, canvas_size: UVec2, seed: u32) -> Vec<u32> {
+        let materials = MatterId::iter().collect::<Vec<_>>();
+        (0..canvas_size.y)
+            .flat_map(|y| (0..canvas_size.x).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let matter = match *self {
+                    BenchmarkScene::Empty => MatterId::Empty,
+                    BenchmarkScene::HalfSand if y >= canvas_size.y / 2 => MatterId::Sand,
+                    BenchmarkScene::HalfSand => MatterId::Empty,
+                    BenchmarkScene::FullWater => MatterId::Water,
+                    BenchmarkScene::NoisyMix => {
+                        let noise = hash((y * canvas_size.x + x) ^ hash(seed));
+                        materials[noise as usize % materials.len()]
+                    }
+                };
+                MatterWithColor::new(matter).value
+            })
+            .collect()
+    }
+}
+
+// Measured throughput of one benchmark
+struct BenchmarkResult {
+    name: String,
+    canvas_size: UVec2,
+    iterations: u32,
+    seconds: f64,
+}
+
+impl BenchmarkResult {
+    fn per_second(&self) -> f64 {
+        self.iterations as f64 / self.seconds
+    }
+}
+
+// Time `run`, which does `iterations` repetitions of the benchmarked work
+fn measure(
+    name: String,
+    canvas_size: UVec2,
+    iterations: u32,
+    run: impl FnOnce(),
+) -> BenchmarkResult {
+    let start = Instant::now();
+    run();
+    let result = BenchmarkResult {
+        name,
+        canvas_size,
+        iterations,
+        seconds: start.elapsed().as_secs_f64(),
+    };
+    println!(
+        "{:<24} {:>5}x{:<5} {:>12.1} /s",
+        result.name,
+        canvas_size.x,
+        canvas_size.y,
+        result.per_second()
+    );
+    result
+}
+
+fn empty_background(canvas_size: UVec2) -> Vec<u32> {
+    vec![BackgroundId::Empty.value(); (canvas_size.x * canvas_size.y) as usize]
+}
+
+// Steps per second of each standard scene, including waiting for the last steps in flight
+fn step_benchmarks(simulator: &mut CASimulator, seed: u32) -> Vec<BenchmarkResult> {
+    let canvas_size = simulator.canvas_size();
+    let background = empty_background(canvas_size);
+    BenchmarkScene::iter()
+        .map(|scene| {
+            simulator.load_grids(&scene.matter_grid(canvas_size, seed), &background);
+            for _ in 0..WARMUP_STEPS {
+                simulator.step(1, false);
+            }
+            simulator.wait_idle();
+            measure(
+                scene.label().to_string(),
+                canvas_size,
+                MEASURED_STEPS,
+                || {
+                    for _ in 0..MEASURED_STEPS {
+                        simulator.step(1, false);
+                    }
+                    simulator.wait_idle();
+                },
+            )
+        })
+        .collect()
+}
+
+// Apply queued painting or grids with a paused step and wait for the gpu
+fn apply_queued(simulator: &mut CASimulator) {
+    simulator.step(1, true);
+    simulator.wait_idle();
+}
+
+// Throughput of painting and grid copies, each applied by a paused step. step_paused is the
+// cost of that step alone, so the rest is the cost of the paint pass and the cpu work.
+fn cpu_benchmarks(simulator: &mut CASimulator) -> Vec<BenchmarkResult> {
+    let canvas_size = simulator.canvas_size();
+    let center = canvas_size.as_ivec2() / 2;
+    let stroke = (0..STROKE_LENGTH)
+        .map(|i| center + IVec2::new(i - STROKE_LENGTH / 2, 0))
+        .collect::<Vec<_>>();
+    let mut results = vec![measure(
+        "step_paused".to_string(),
+        canvas_size,
+        STROKES,
+        || {
+            for _ in 0..STROKES {
+                apply_queued(simulator);
+            }
+        },
+    )];
+    for radius in BRUSH_RADII {
+        results.push(measure(
+            format!("draw_matter_r{}", radius),
+            canvas_size,
+            STROKES,
+            || {
+                for _ in 0..STROKES {
+                    simulator.draw_matter(&stroke, radius, MatterId::Sand);
+                    apply_queued(simulator);
+                }
+            },
+        ));
+    }
+    let stamp = simulator.copy_region(center - 128, center + 127);
+    results.push(measure(
+        "paste_stamp_256".to_string(),
+        canvas_size,
+        STROKES,
+        || {
+            for _ in 0..STROKES {
+                simulator.paste_stamp(center - 128, &stamp, PasteMode::Overwrite);
+                apply_queued(simulator);
+            }
+        },
+    ));
+    let (matter, background) = simulator.read_grids();
+    results.push(measure(
+        "read_grids".to_string(),
+        canvas_size,
+        GRID_COPIES,
+        || {
+            for _ in 0..GRID_COPIES {
+                drop(simulator.read_grids());
+            }
+        },
+    ));
+    results.push(measure(
+        "load_grids".to_string(),
+        canvas_size,
+        GRID_COPIES,
+        || {
+            for _ in 0..GRID_COPIES {
+                simulator.load_grids(&matter, &background);
+                apply_queued(simulator);
+            }
+        },
+    ));
+    results
+}
+
+// Write results as csv with header device,benchmark,canvas_width,canvas_height,iterations,
+// total_s,per_second
+fn write_csv(device: &str, results: &[BenchmarkResult], path: &Path) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "device,benchmark,canvas_width,canvas_height,iterations,total_s,per_second"
+    )?;
+    for result in results {
+        writeln!(
+            file,
+            "{},{},{},{},{},{:.6},{:.3}",
+            device.replace(',', " "),
+            result.name,
+            result.canvas_size.x,
+            result.canvas_size.y,
+            result.iterations,
+            result.seconds,
+            result.per_second()
+        )?;
+    }
+    file.flush()
+}
+
+// Run the simulation and cpu benchmarks and write their results to `output`. Canvas sizes the
+// device can't fit are skipped.
+pub fn run_benchmarks(config: &Config, output: &Path) -> Result<(), String> {
+    let context = VulkanoContext::new(config.vulkano_config());
+    println!("Benchmarking on {}", context.device_name());
+    let mut results = vec![];
+    let mut simulator = None;
+    for size in CANVAS_SIZES {
+        let sized_config = config.with_canvas_size(UVec2::new(size, size));
+        if let Err(e) = sized_config
+            .validate()
+            .and_then(|()| sized_config.validate_device(&context.device()))
+        {
+            println!("Skipping {}x{}: {}", size, size, e);
+            continue;
+        }
+        let mut sized_simulator =
+            CASimulator::new(context.compute_queue(), sized_config.canvas_size());
+        sized_simulator.set_seed(config.seed);
+        reload_reactions(&mut sized_simulator);
+        results.extend(step_benchmarks(&mut sized_simulator, config.seed));
+        simulator = Some(sized_simulator);
+    }
+    let mut simulator = simulator.ok_or("No benchmark canvas size fits the device")?;
+    results.extend(cpu_benchmarks(&mut simulator));
+
+    write_csv(context.device_name(), &results, output)
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
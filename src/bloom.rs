@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{ImageUsage, StorageImage},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    sync::GpuFuture,
+};
+use vulkano_util::renderer::DeviceImageView;
+
+use bevy::math::UVec2;
+
+use crate::{
+    particle_simulator::work_groups,
+    utils::{create_compute_pipeline, storage_image_desc},
+    LOCAL_SIZE_X, LOCAL_SIZE_Y,
+};
+
+mod bloom_blur_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/bloom_blur.glsl"
+    }
+}
+
+// Glow post-processing. Blurs the emission image of the canvas into a glow image which is
+// added over the canvas when rendering.
+pub struct BloomPass {
+    gfx_queue: Arc<Queue>,
+    canvas_size: UVec2,
+    blur_pipeline: Arc<ComputePipeline>,
+    // Result of the horizontal blur
+    blurred_x: DeviceImageView,
+    glow: DeviceImageView,
+}
+
+// Creates a canvas sized image for blurring
+fn blur_image(gfx_queue: &Arc<Queue>, canvas_size: UVec2) -> DeviceImageView {
+    StorageImage::general_purpose_image_view(
+        gfx_queue.clone(),
+        canvas_size.to_array(),
+        Format::R16G16B16A16_SFLOAT,
+        ImageUsage {
+            sampled: true,
+            storage: true,
+            ..ImageUsage::none()
+        },
+    )
+    .unwrap()
+}
+
+impl BloomPass {
+    pub fn new(gfx_queue: Arc<Queue>, canvas_size: UVec2) -> BloomPass {
+        let spec_const = bloom_blur_cs::SpecializationConstants {
+            canvas_size_x: canvas_size.x as i32,
+            canvas_size_y: canvas_size.y as i32,
+            constant_2: LOCAL_SIZE_X,
+            constant_3: LOCAL_SIZE_Y,
+        };
+        let blur_shader = bloom_blur_cs::load(gfx_queue.device().clone()).unwrap();
+        // This must match the shader and inputs in blur
+        let descriptor_layout = vec![(0, storage_image_desc()), (1, storage_image_desc())];
+        let blur_pipeline = create_compute_pipeline(
+            gfx_queue.clone(),
+            blur_shader.entry_point("main").unwrap(),
+            descriptor_layout,
+            &spec_const,
+        );
+        BloomPass {
+            blurred_x: blur_image(&gfx_queue, canvas_size),
+            glow: blur_image(&gfx_queue, canvas_size),
+            gfx_queue,
+            canvas_size,
+            blur_pipeline,
+        }
+    }
+
+    // Get the blurred glow image for rendering
+    pub fn glow_image(&self) -> DeviceImageView {
+        self.glow.clone()
+    }
+
+    // Blur emission image into the glow image. Radius is in canvas pixels.
+    pub fn apply<F>(
+        &mut self,
+        before_future: F,
+        emission: DeviceImageView,
+        radius: u32,
+        intensity: f32,
+    ) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        self.blur(
+            &mut builder,
+            emission,
+            self.blurred_x.clone(),
+            [1, 0],
+            radius,
+            1.0,
+        );
+        self.blur(
+            &mut builder,
+            self.blurred_x.clone(),
+            self.glow.clone(),
+            [0, 1],
+            radius,
+            intensity,
+        );
+        let command_buffer = builder.build().unwrap();
+        before_future
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .boxed()
+    }
+
+    // Append a blur of src into dst along direction
+    fn blur(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        src: DeviceImageView,
+        dst: DeviceImageView,
+        direction: [i32; 2],
+        radius: u32,
+        intensity: f32,
+    ) {
+        let pipeline_layout = self.blur_pipeline.layout();
+        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(
+            desc_layout.clone(),
+            [
+                WriteDescriptorSet::image_view(0, src),
+                WriteDescriptorSet::image_view(1, dst),
+            ],
+        )
+        .unwrap();
+        let push_constants = bloom_blur_cs::ty::PushConstants {
+            direction,
+            radius: radius as i32,
+            intensity,
+        };
+        builder
+            .bind_pipeline_compute(self.blur_pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
+            .push_constants(pipeline_layout.clone(), 0, push_constants)
+            .dispatch(work_groups(self.canvas_size))
+            .unwrap();
+    }
+}
@@ -60,6 +60,31 @@ impl OrthographicCamera {
         let pixels_ratio = visible_vertical_pixels as f32 / actual_vertical_pixels as f32;
         self.scale = pixels_ratio;
     }
+
+    // Scale the zoom while keeping the world position under `screen_offset` (pixels from
+    // screen center, y up) in place. Scale is kept between min_scale and max_scale.
+    pub fn zoom_around(
+        &mut self,
+        screen_offset: Vec2,
+        factor: f32,
+        min_scale: f32,
+        max_scale: f32,
+    ) {
+        let new_scale = (self.scale * factor).clamp(min_scale, max_scale);
+        self.pos += screen_offset * (new_scale - self.scale);
+        self.scale = new_scale;
+    }
+
+    // Move the view by screen pixels, e.g. from a mouse drag
+    pub fn pan_pixels(&mut self, screen_delta: Vec2) {
+        self.pos += screen_delta * self.scale;
+    }
+
+    // Keep the screen center within the canvas so that it can't be scrolled off-screen
+    pub fn clamp_to_canvas(&mut self, canvas_size: Vec2) {
+        let half_canvas = canvas_size / 2.0;
+        self.pos = self.pos.clamp(-half_canvas, half_canvas);
+    }
 }
 
 impl Default for OrthographicCamera {
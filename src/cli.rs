@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use particle_simulation::Config;
+
+const USAGE: &str = "Usage: particle_simulation [options] [scene]
+
+Options:
+  --headless <STEPS>        Run this many steps without a window, then exit
+  --output <PATH>           Where a headless run saves its final scene
+  --benchmark <PATH>        Run the benchmark suite and write its results as csv, then exit";
+
+// Options of the binary that the simulation itself doesn't use
+#[derive(Debug, Clone, Default)]
+pub struct CliOptions {
+    // Steps of a run without window
+    pub headless_steps: Option<u32>,
+    pub output: Option<PathBuf>,
+    // Where benchmark results are written, runs the benchmarks instead of the simulation
+    pub benchmark_output: Option<PathBuf>,
+}
+
+impl CliOptions {
+    pub fn usage() -> String {
+        format!("{}\n{}", USAGE, Config::options_usage())
+    }
+
+    // Parse arguments without the program name. Returns Ok(None) if help was requested.
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Option<(CliOptions, Config)>, String> {
+        let mut options = CliOptions::default();
+        let mut config_args = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !matches!(arg.as_str(), "--headless" | "--output" | "--benchmark") {
+                config_args.push(arg);
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} expects a value", arg))?;
+            match arg.as_str() {
+                "--headless" => {
+                    options.headless_steps = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("{} expects a number, got '{}'", arg, value))?,
+                    )
+                }
+                "--output" => options.output = Some(PathBuf::from(value)),
+                _ => options.benchmark_output = Some(PathBuf::from(value)),
+            }
+        }
+        let config = match Config::from_args(config_args)? {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        if options.output.is_some() && options.headless_steps.is_none() {
+            return Err("--output is only used with --headless".to_string());
+        }
+        if options.benchmark_output.is_some()
+            && (config.scene.is_some() || options.headless_steps.is_some())
+        {
+            return Err(
+                "--benchmark runs its own scenes without --scene or --headless".to_string(),
+            );
+        }
+        Ok(Some((options, config)))
+    }
+}
@@ -0,0 +1,201 @@
+use std::{path::PathBuf, sync::Arc};
+
+use bevy::{math::UVec2, window::PresentMode};
+use vulkano::device::{physical::PhysicalDeviceType, Device};
+use vulkano_util::context::VulkanoConfig;
+
+use crate::{LOCAL_SIZE_X, LOCAL_SIZE_Y};
+
+// Options parsed by Config, binaries list their own next to these
+const OPTIONS_USAGE: &str = "  --canvas-size <W>x<H>     Simulated cells, multiples of 32 (default 1024x1024, or the scene's)
+  --window-size <W>x<H>     Window size in logical pixels (default 1024x1024)
+  --sim-fps <FPS>           Simulation steps per second (default 60)
+  --seed <N>                Salt of the simulation's random numbers (default 0)
+  --present-mode <MODE>     immediate, mailbox, fifo, auto-vsync or auto-no-vsync (default immediate)
+  --scene <PATH>            Scene to start with
+  --gpu-timings <PATH>      Where gpu timings are exported (default gpu_timings.csv)
+  --software-device         Use a cpu Vulkan implementation such as lavapipe
+  --help                    Print this message";
+
+// Runtime configuration parsed from the command line
+#[derive(Debug, Clone)]
+pub struct Config {
+    // None until resolved from a scene or the default, see `canvas_size`
+    canvas_size: Option<UVec2>,
+    pub window_size: UVec2,
+    pub sim_fps: f64,
+    pub seed: u32,
+    pub present_mode: PresentMode,
+    pub scene: Option<PathBuf>,
+    pub gpu_timings_path: PathBuf,
+    pub software_device: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            canvas_size: None,
+            window_size: UVec2::new(1024, 1024),
+            sim_fps: 60.0,
+            seed: 0,
+            present_mode: PresentMode::Immediate,
+            scene: None,
+            gpu_timings_path: PathBuf::from("gpu_timings.csv"),
+            software_device: false,
+        }
+    }
+}
+
+fn parse_size(value: &str, option: &str) -> Result<UVec2, String> {
+    let size = value
+        .split_once('x')
+        .and_then(|(x, y)| Some(UVec2::new(x.parse().ok()?, y.parse().ok()?)))
+        .filter(|size| size.x > 0 && size.y > 0);
+    size.ok_or_else(|| format!("{} expects <width>x<height>, got '{}'", option, value))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, option: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got '{}'", option, value))
+}
+
+fn parse_present_mode(value: &str) -> Result<PresentMode, String> {
+    match value {
+        "immediate" => Ok(PresentMode::Immediate),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        "fifo" => Ok(PresentMode::Fifo),
+        "auto-vsync" => Ok(PresentMode::AutoVsync),
+        "auto-no-vsync" => Ok(PresentMode::AutoNoVsync),
+        _ => Err(format!("unknown present mode '{}'", value)),
+    }
+}
+
+impl Config {
+    pub fn options_usage() -> &'static str {
+        OPTIONS_USAGE
+    }
+
+    // Parse arguments without the program name. Returns Ok(None) if help was requested.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Config>, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Ok(None);
+            }
+            if !arg.starts_with("--") {
+                config.scene = Some(PathBuf::from(arg));
+                continue;
+            }
+            if arg == "--software-device" {
+                config.software_device = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} expects a value", arg))?;
+            match arg.as_str() {
+                "--canvas-size" => config.canvas_size = Some(parse_size(&value, &arg)?),
+                "--window-size" => config.window_size = parse_size(&value, &arg)?,
+                "--sim-fps" => {
+                    config.sim_fps = parse_number(&value, &arg)?;
+                    if config.sim_fps <= 0.0 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                }
+                "--seed" => config.seed = parse_number(&value, &arg)?,
+                "--present-mode" => config.present_mode = parse_present_mode(&value)?,
+                "--scene" => config.scene = Some(PathBuf::from(value)),
+                "--gpu-timings" => config.gpu_timings_path = PathBuf::from(value),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+        Ok(Some(config))
+    }
+
+    // Canvas size, from the command line, the initial scene or the default
+    pub fn canvas_size(&self) -> UVec2 {
+        self.canvas_size.unwrap_or_else(|| UVec2::new(1024, 1024))
+    }
+
+    // Same configuration with another canvas size, e.g. for benchmarks
+    pub fn with_canvas_size(&self, canvas_size: UVec2) -> Config {
+        Config {
+            canvas_size: Some(canvas_size),
+            ..self.clone()
+        }
+    }
+
+    // Device selection, restricted to cpu implementations with --software-device
+    pub fn vulkano_config(&self) -> VulkanoConfig {
+        let vulkano_config = VulkanoConfig::default();
+        if !self.software_device {
+            return vulkano_config;
+        }
+        let default_filter = vulkano_config.device_filter_fn.clone();
+        VulkanoConfig {
+            device_filter_fn: Arc::new(move |p| {
+                default_filter(p) && p.properties().device_type == PhysicalDeviceType::Cpu
+            }),
+            ..vulkano_config
+        }
+    }
+
+    // Take the canvas size of the initial scene unless one was given
+    pub fn resolve_canvas_size(&mut self, scene_canvas_size: UVec2) -> Result<(), String> {
+        match self.canvas_size {
+            Some(canvas_size) if canvas_size != scene_canvas_size => Err(format!(
+                "--canvas-size {}x{} doesn't match the scene's {}x{}",
+                canvas_size.x, canvas_size.y, scene_canvas_size.x, scene_canvas_size.y
+            )),
+            _ => {
+                self.canvas_size = Some(scene_canvas_size);
+                Ok(())
+            }
+        }
+    }
+
+    // Checks that don't need a device
+    pub fn validate(&self) -> Result<(), String> {
+        let canvas_size = self.canvas_size();
+        if canvas_size.x % LOCAL_SIZE_X != 0 || canvas_size.y % LOCAL_SIZE_Y != 0 {
+            return Err(format!(
+                "canvas size {}x{} must be a multiple of {}x{}",
+                canvas_size.x, canvas_size.y, LOCAL_SIZE_X, LOCAL_SIZE_Y
+            ));
+        }
+        Ok(())
+    }
+
+    // Check that the canvas fits the limits of a device
+    pub fn validate_device(&self, device: &Device) -> Result<(), String> {
+        let canvas_size = self.canvas_size();
+        let properties = device.physical_device().properties();
+        let max_image_size = properties.max_image_dimension2_d;
+        if canvas_size.max_element() > max_image_size {
+            return Err(format!(
+                "canvas size {}x{} exceeds the device's maximum image size {}",
+                canvas_size.x, canvas_size.y, max_image_size
+            ));
+        }
+        let [max_groups_x, max_groups_y, _] = properties.max_compute_work_group_count;
+        if canvas_size.x / LOCAL_SIZE_X > max_groups_x
+            || canvas_size.y / LOCAL_SIZE_Y > max_groups_y
+        {
+            return Err(format!(
+                "canvas size {}x{} needs more work groups than the device's {}x{}",
+                canvas_size.x, canvas_size.y, max_groups_x, max_groups_y
+            ));
+        }
+        // Light is the largest per cell buffer with two floats per cell
+        let largest_buffer = canvas_size.x as u64 * canvas_size.y as u64 * 8;
+        if largest_buffer > properties.max_storage_buffer_range as u64 {
+            return Err(format!(
+                "canvas size {}x{} needs {} byte buffers, the device allows {}",
+                canvas_size.x, canvas_size.y, largest_buffer, properties.max_storage_buffer_range
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+
+use crate::matter::MatterId;
+
+// Sent after every unpaused simulation step. Steps are numbered from 0.
+#[derive(Debug, Copy, Clone)]
+pub struct SimulationStepped {
+    pub step: u32,
+}
+
+// Sent when matter is painted with the brush
+#[derive(Debug, Copy, Clone)]
+pub struct MatterPainted {
+    // Inclusive corners of the painted cells' bounds
+    pub region: (IVec2, IVec2),
+    pub material: MatterId,
+}
+
+// Sent when the amount of a material inside a sensor grew, see sensors.rs. Counts are read
+// back from the gpu, so this arrives a few steps after the matter entered.
+#[derive(Debug, Copy, Clone)]
+pub struct MatterEntered {
+    pub sensor: Entity,
+    pub material: MatterId,
+    pub count: u32,
+}
@@ -1,10 +1,32 @@
-use crate::DynamicSettings;
+use std::path::Path;
+
+use crate::{
+    background::BackgroundId,
+    config::Config,
+    matter::{matter_name, MatterWithColor},
+    minimap::Minimap,
+    particle_simulator::{BoundaryMode, CASimulator, Edge, ViewMode},
+    profiler::GpuTimings,
+    reactions::{reload_reactions, REACTIONS_PATH},
+    scene::{scene_files, SceneLibrary, SceneRequest},
+    shader_reload::ShaderReload,
+    stamps::{load_stamp, save_stamp, stamp_files, Clipboard, PasteMode},
+    stats::MatterCountHistory,
+    world_objects::{Drain, Emitter, Shape, ShapeKind},
+    DynamicSettings, InspectedCell, Tool,
+};
 use bevy::{
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     prelude::*,
 };
 use bevy_vulkano::{
-    egui_winit_vulkano::{egui, egui::Ui},
+    egui_winit_vulkano::{
+        egui,
+        egui::{
+            plot::{Legend, Line, Plot, Value, Values},
+            Ui,
+        },
+    },
     BevyVulkanoWindows,
 };
 
@@ -21,6 +43,18 @@ pub fn user_interface(
     vulkano_windows: NonSend<BevyVulkanoWindows>,
     diagnostics: Res<Diagnostics>,
     mut settings: ResMut<DynamicSettings>,
+    count_history: Res<MatterCountHistory>,
+    gpu_timings: Res<GpuTimings>,
+    shader_reload: Res<ShaderReload>,
+    inspected: Res<InspectedCell>,
+    mut minimap: ResMut<Minimap>,
+    mut commands: Commands,
+    mut emitters: Query<(Entity, &mut Emitter)>,
+    drains: Query<(Entity, &Drain)>,
+    mut simulator: ResMut<CASimulator>,
+    mut clipboard: ResMut<Clipboard>,
+    mut scenes: ResMut<SceneLibrary>,
+    config: Res<Config>,
 ) {
     let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
     let ctx = gui.context();
@@ -34,8 +68,32 @@ pub fn user_interface(
                     sized_text(ui, format!("FPS: {:.2}", avg), size);
                 }
             }
+            gpu_timings_ui(ui, &gpu_timings, &config.gpu_timings_path, size);
+            shader_errors_ui(ui, &shader_reload);
             ui.heading("Settings");
             ui.add(egui::Slider::new(&mut settings.brush_radius, 0.5..=40.0).text("Brush Radius"));
+            ui.checkbox(&mut minimap.visible, "Minimap");
+            ui.checkbox(&mut settings.glow, "Glow");
+            if settings.glow {
+                ui.add(egui::Slider::new(&mut settings.glow_radius, 1..=32).text("Glow Radius"));
+                ui.add(
+                    egui::Slider::new(&mut settings.glow_intensity, 0.0..=4.0)
+                        .text("Glow Intensity"),
+                );
+            }
+
+            // Debug visualization of the color pass
+            egui::ComboBox::from_label("View")
+                .selected_text(format!("{:?}", settings.view_mode))
+                .show_ui(ui, |ui| {
+                    for view_mode in ViewMode::iter() {
+                        ui.selectable_value(
+                            &mut settings.view_mode,
+                            view_mode,
+                            format!("{:?}", view_mode),
+                        );
+                    }
+                });
 
             // Selectable matter
             egui::ComboBox::from_label("Matter")
@@ -49,5 +107,332 @@ pub fn user_interface(
                         );
                     }
                 });
+
+            ui.heading("World objects");
+            world_objects_ui(ui, &mut settings, &mut commands, &mut emitters, &drains);
+
+            ui.heading("Scene");
+            scene_ui(ui, &mut scenes);
+
+            ui.heading("Stamps");
+            stamps_ui(ui, &mut clipboard, &mut simulator);
+
+            ui.heading("Boundaries");
+            boundaries_ui(ui, &mut settings);
+
+            ui.heading("Lighting");
+            lighting_ui(ui, &mut settings);
+
+            ui.heading("Inspector");
+            inspected_cell_ui(ui, *inspected, size);
+
+            ui.heading("Matter");
+            if ui
+                .button(format!("Reload reactions from {}", REACTIONS_PATH))
+                .clicked()
+            {
+                reload_reactions(&mut simulator);
+            }
+            ui.checkbox(
+                &mut settings.check_conservation,
+                "Check matter conservation",
+            );
+            matter_counts_ui(ui, &count_history, size);
+        });
+}
+
+// Average gpu time of each pass and csv export
+fn gpu_timings_ui(ui: &mut Ui, gpu_timings: &GpuTimings, path: &Path, size: f32) {
+    for &pass in gpu_timings.passes.keys() {
+        if let Some(avg) = gpu_timings.average(pass) {
+            sized_text(ui, format!("{}: {:.3} ms", pass, avg), size);
+        }
+    }
+    if ui.button("Export GPU timings").clicked() {
+        match gpu_timings.write_csv(path) {
+            Ok(()) => bevy::log::info!("Wrote gpu timings to {}", path.display()),
+            Err(e) => bevy::log::error!("Failed to write gpu timings: {}", e),
+        }
+    }
+}
+
+// Compile errors of reloaded shaders, whose previous pipelines are still in use
+fn shader_errors_ui(ui: &mut Ui, shader_reload: &ShaderReload) {
+    for error in shader_reload.errors.iter() {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+}
+
+// Tool selection and a list of emitters and drains that can be edited and removed
+fn world_objects_ui(
+    ui: &mut Ui,
+    settings: &mut DynamicSettings,
+    commands: &mut Commands,
+    emitters: &mut Query<(Entity, &mut Emitter)>,
+    drains: &Query<(Entity, &Drain)>,
+) {
+    egui::ComboBox::from_label("Tool")
+        .selected_text(format!("{:?}", settings.tool))
+        .show_ui(ui, |ui| {
+            for tool in Tool::iter() {
+                ui.selectable_value(&mut settings.tool, tool, format!("{:?}", tool));
+            }
+        });
+    match settings.tool {
+        Tool::Background => {
+            egui::ComboBox::from_label("Background")
+                .selected_text(format!("{:?}", settings.draw_background))
+                .show_ui(ui, |ui| {
+                    for background in BackgroundId::iter() {
+                        ui.selectable_value(
+                            &mut settings.draw_background,
+                            background,
+                            format!("{:?}", background),
+                        );
+                    }
+                });
+        }
+        Tool::Emitter | Tool::Drain => {
+            egui::ComboBox::from_label("Shape")
+                .selected_text(format!("{:?}", settings.world_object_shape))
+                .show_ui(ui, |ui| {
+                    for shape in ShapeKind::iter() {
+                        ui.selectable_value(
+                            &mut settings.world_object_shape,
+                            shape,
+                            format!("{:?}", shape),
+                        );
+                    }
+                });
+            if settings.tool == Tool::Emitter {
+                ui.add(
+                    egui::Slider::new(&mut settings.emitter_rate, 0.1..=100.0).text("Emitter Rate"),
+                );
+            }
+        }
+        Tool::Explode => {
+            ui.add(
+                egui::Slider::new(&mut settings.explosion_strength, 0.5..=10.0)
+                    .text("Explosion Strength"),
+            );
+        }
+        _ => (),
+    }
+    for (entity, mut emitter) in emitters.iter_mut() {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Emitter {:?} at {}",
+                emitter.matter,
+                shape_label(emitter.shape)
+            ));
+            ui.add(egui::Slider::new(&mut emitter.rate, 0.1..=100.0).text("Rate"));
+            if ui.button("Remove").clicked() {
+                commands.entity(entity).despawn();
+            }
+        });
+    }
+    for (entity, drain) in drains.iter() {
+        ui.horizontal(|ui| {
+            ui.label(format!("Drain at {}", shape_label(drain.shape)));
+            if ui.button("Remove").clicked() {
+                commands.entity(entity).despawn();
+            }
+        });
+    }
+}
+
+// Metadata of the current scene, saving it and a browser of saved scenes
+fn scene_ui(ui: &mut Ui, scenes: &mut SceneLibrary) {
+    ui.horizontal(|ui| {
+        ui.label("Name");
+        ui.text_edit_singleline(&mut scenes.name);
+    });
+    ui.label("Description");
+    ui.text_edit_multiline(&mut scenes.description);
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut scenes.path);
+        if ui.button("Load").clicked() {
+            scenes.request_load();
+        }
+        if ui.button("Save").clicked() {
+            scenes.request = Some(SceneRequest::Save(scenes.path.clone().into()));
+        }
+    });
+    match &scenes.status {
+        Some(Ok(message)) => {
+            ui.label(message);
+        }
+        Some(Err(e)) => {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+        None => (),
+    }
+    for path in scene_files() {
+        ui.horizontal(|ui| {
+            ui.label(path.display().to_string());
+            if ui.button("Open").clicked() {
+                scenes.path = path.display().to_string();
+                scenes.request_load();
+            }
+        });
+    }
+}
+
+// Copying the selection, transforming the stamp and the library of saved stamps
+fn stamps_ui(ui: &mut Ui, clipboard: &mut Clipboard, simulator: &mut CASimulator) {
+    if let Some((min, max)) = clipboard.selection {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Selection ({}, {}) to ({}, {})",
+                min.x, min.y, max.x, max.y
+            ));
+            if ui.button("Copy").clicked() {
+                clipboard.copy(simulator);
+            }
+        });
+    }
+    egui::ComboBox::from_label("Paste Mode")
+        .selected_text(format!("{:?}", clipboard.paste_mode))
+        .show_ui(ui, |ui| {
+            for paste_mode in PasteMode::iter() {
+                ui.selectable_value(
+                    &mut clipboard.paste_mode,
+                    paste_mode,
+                    format!("{:?}", paste_mode),
+                );
+            }
+        });
+    if let Some(stamp) = clipboard.stamp.clone() {
+        ui.horizontal(|ui| {
+            ui.label(format!("Stamp {}x{}", stamp.width, stamp.height));
+            if ui.button("Rotate").clicked() {
+                clipboard.stamp = Some(stamp.rotated());
+            }
+            if ui.button("Flip H").clicked() {
+                clipboard.stamp = Some(stamp.flipped_horizontal());
+            }
+            if ui.button("Flip V").clicked() {
+                clipboard.stamp = Some(stamp.flipped_vertical());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut clipboard.stamp_name);
+            if ui.button("Save stamp").clicked() {
+                match save_stamp(&stamp, &clipboard.stamp_name) {
+                    Ok(path) => bevy::log::info!("Saved stamp to {}", path.display()),
+                    Err(e) => bevy::log::error!("{}", e),
+                }
+            }
+        });
+    }
+    for path in stamp_files() {
+        ui.horizontal(|ui| {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            ui.label(name.as_ref());
+            if ui.button("Load").clicked() {
+                match load_stamp(&path) {
+                    Ok(stamp) => clipboard.stamp = Some(stamp),
+                    Err(e) => bevy::log::error!("{}", e),
+                }
+            }
+        });
+    }
+}
+
+fn shape_label(shape: Shape) -> String {
+    match shape {
+        Shape::Circle { center, radius } => format!("({}, {}) r {}", center.x, center.y, radius),
+        Shape::Rect { min, max } => {
+            format!("({}, {}) to ({}, {})", min.x, min.y, max.x, max.y)
+        }
+    }
+}
+
+// Boundary mode of each canvas edge
+fn boundaries_ui(ui: &mut Ui, settings: &mut DynamicSettings) {
+    for edge in Edge::iter() {
+        let mut mode = settings.boundaries.get(edge);
+        egui::ComboBox::from_label(format!("{:?}", edge))
+            .selected_text(format!("{:?}", mode))
+            .show_ui(ui, |ui| {
+                for boundary_mode in BoundaryMode::iter() {
+                    ui.selectable_value(&mut mode, boundary_mode, format!("{:?}", boundary_mode));
+                }
+            });
+        if mode != settings.boundaries.get(edge) {
+            settings.boundaries.set(edge, mode);
+        }
+    }
+}
+
+// Light settings and per matter light properties
+fn lighting_ui(ui: &mut Ui, settings: &mut DynamicSettings) {
+    ui.checkbox(&mut settings.lighting.enabled, "Lighting");
+    if !settings.lighting.enabled {
+        return;
+    }
+    ui.add(egui::Slider::new(&mut settings.lighting.sky_light, 0.0..=1.0).text("Sky Light"));
+    ui.add(
+        egui::Slider::new(&mut settings.lighting.ambient_light, 0.0..=1.0).text("Ambient Light"),
+    );
+    ui.add(
+        egui::Slider::new(&mut settings.lighting.background_opacity, 0.0..=1.0)
+            .text("Background Opacity"),
+    );
+    ui.collapsing("Matter light", |ui| {
+        for matter in MatterId::iter() {
+            let properties = &mut settings.matter_properties[matter as usize];
+            ui.label(format!("{:?}", matter));
+            ui.add(egui::Slider::new(&mut properties.opacity, 0.0..=1.0).text("Opacity"));
+            ui.add(egui::Slider::new(&mut properties.emission, 0.0..=1.0).text("Emission"));
+        }
+    });
+}
+
+// Contents of the cell under the cursor
+fn inspected_cell_ui(ui: &mut Ui, inspected: InspectedCell, size: f32) {
+    if let Some((pos, value)) = inspected.0 {
+        let matter = MatterWithColor::from(value);
+        let [r, g, b] = matter.color_rgb();
+        sized_text(ui, format!("Cell: ({}, {})", pos.x, pos.y), size);
+        sized_text(
+            ui,
+            format!(
+                "Matter: {} ({})",
+                matter_name(matter.matter_id()),
+                matter.matter_id()
+            ),
+            size,
+        );
+        sized_text(ui, format!("Value: {:#010x}", value), size);
+        ui.horizontal(|ui| {
+            sized_text(ui, format!("Color: #{:02x}{:02x}{:02x}", r, g, b), size);
+            ui.colored_label(egui::Color32::from_rgb(r, g, b), "■■");
+        });
+    }
+}
+
+// Latest population of each matter and a plot of their history
+fn matter_counts_ui(ui: &mut Ui, count_history: &MatterCountHistory, size: f32) {
+    if let Some(latest) = count_history.latest() {
+        for matter in MatterId::iter() {
+            sized_text(ui, format!("{:?}: {}", matter, latest.get(matter)), size);
+        }
+    }
+    Plot::new("matter_counts")
+        .width(300.0)
+        .height(150.0)
+        .include_y(0.0)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            for matter in MatterId::iter().filter(|&matter| matter != MatterId::Empty) {
+                let values = count_history
+                    .samples
+                    .iter()
+                    .map(|counts| Value::new(counts.step, counts.get(matter)));
+                plot_ui.line(
+                    Line::new(Values::from_values_iter(values)).name(format!("{:?}", matter)),
+                );
+            }
         });
 }
@@ -0,0 +1,74 @@
+use std::{path::Path, time::Instant};
+
+use bevy::math::Vec2;
+use vulkano_util::context::VulkanoContext;
+
+use particle_simulation::{
+    particle_simulator::CASimulator,
+    reactions::reload_reactions,
+    scene::{save_scene, Scene},
+    world_objects::WorldObject,
+    Config,
+};
+
+// Run the simulation without a window for `steps` steps and save the final scene if an output
+// path is given
+pub fn run_headless(
+    config: &Config,
+    scene: Option<Scene>,
+    steps: u32,
+    output: Option<&Path>,
+) -> Result<(), String> {
+    let context = VulkanoContext::new(config.vulkano_config());
+    config.validate_device(&context.device())?;
+    let mut simulator = CASimulator::new(context.compute_queue(), config.canvas_size());
+    simulator.set_seed(config.seed);
+    reload_reactions(&mut simulator);
+    let mut scene = scene.unwrap_or_else(|| Scene {
+        name: "Headless run".to_string(),
+        description: String::new(),
+        canvas_size: config.canvas_size(),
+        camera_pos: Vec2::ZERO,
+        camera_scale: 1.0,
+        matter: vec![],
+        background: vec![],
+        emitters: vec![],
+        drains: vec![],
+    });
+    if !scene.matter.is_empty() {
+        simulator.load_grids(&scene.matter, &scene.background);
+    }
+    simulator.set_world_objects(
+        scene
+            .emitters
+            .iter()
+            .map(WorldObject::from)
+            .chain(scene.drains.iter().map(WorldObject::from))
+            .collect(),
+    );
+
+    let start = Instant::now();
+    for _ in 0..steps {
+        simulator.step(1, false);
+    }
+    simulator.wait_idle();
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "Ran {} steps of a {}x{} canvas in {:.3} s ({:.1} steps/s)",
+        steps,
+        config.canvas_size().x,
+        config.canvas_size().y,
+        seconds,
+        steps as f64 / seconds
+    );
+
+    if let Some(output) = output {
+        let (matter, background) = simulator.read_grids();
+        scene.canvas_size = simulator.canvas_size();
+        scene.matter = matter;
+        scene.background = background;
+        save_scene(&scene, output)?;
+        println!("Saved {}", output.display());
+    }
+    Ok(())
+}
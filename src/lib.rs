@@ -0,0 +1,510 @@
+// Falling sand simulation on the gpu. Add SandSimPlugin after VulkanoWinitPlugin to embed it,
+// and SandSimRenderPlugin and SandSimGuiPlugin to draw it, see main.rs.
+pub mod background;
+pub mod bloom;
+pub mod camera;
+pub mod config;
+pub mod events;
+pub mod gui;
+pub mod matter;
+pub mod minimap;
+pub mod particle_simulator;
+pub mod particles;
+pub mod profiler;
+mod quad_pipeline;
+pub mod reactions;
+pub mod render;
+pub mod scene;
+pub mod sensors;
+pub mod shader_reload;
+pub mod stamps;
+pub mod stats;
+pub mod utils;
+mod vertex;
+pub mod world_objects;
+
+use std::sync::Arc;
+
+use bevy::{input::mouse::MouseWheel, prelude::*, time::FixedTimestep};
+
+use bevy_vulkano::BevyVulkanoWindows;
+use strum_macros::EnumIter;
+use vulkano::{image::ImageViewAbstract, sync::GpuFuture};
+use vulkano_util::context::VulkanoContext;
+
+use crate::{
+    background::BackgroundId,
+    bloom::BloomPass,
+    camera::OrthographicCamera,
+    events::{MatterEntered, MatterPainted, SimulationStepped},
+    gui::user_interface,
+    matter::{matter_properties_table, MatterId, MatterProperties},
+    minimap::Minimap,
+    particle_simulator::{Boundaries, CASimulator, LightSettings, ViewMode},
+    particles::Ejection,
+    profiler::{record_gpu_timings, GpuTimings},
+    reactions::reload_reactions,
+    render::FillScreenRenderPass,
+    scene::{handle_scene_requests, load_scene, SceneLibrary},
+    sensors::{collect_sensors, emit_sensor_events, Sensor},
+    shader_reload::{reload_shaders, ShaderReload},
+    stamps::{select_and_paste, Clipboard},
+    stats::{record_matter_counts, MatterCountHistory},
+    utils::{brush_bounds, cursor_to_world, get_canvas_line, MousePos},
+    world_objects::{collect_world_objects, place_world_objects, Drain, Emitter, ShapeKind},
+};
+
+pub use crate::config::Config;
+
+//CONSTANTS
+//screen and camera constants
+pub const CLEAR_COLOR: [f32; 4] = [1.0; 4];
+// Camera move speed in screen pixels per second
+pub const CAMERA_MOVE_SPEED: f32 = 200.0;
+pub const CAMERA_ZOOM_FACTOR: f32 = 1.05;
+// Camera scale is world pixels per screen pixel
+pub const CAMERA_MIN_SCALE: f32 = 0.02;
+pub const CAMERA_MAX_SCALE: f32 = 8.0;
+
+//gpu multithreading constants, canvas sizes must be multiples of these
+pub const LOCAL_SIZE_X: u32 = 32;
+pub const LOCAL_SIZE_Y: u32 = 32;
+
+// Creates our simulation pipeline
+fn setup(mut commands: Commands, context: Res<VulkanoContext>, config: Res<Config>) {
+    // Create simple orthographic camera
+    let mut camera = OrthographicCamera::default();
+    let canvas_size = config.canvas_size();
+    camera.zoom_to_fit_vertical_pixels(canvas_size.y, config.window_size.y);
+
+    let mut simulator = CASimulator::new(context.compute_queue(), canvas_size);
+    simulator.set_seed(config.seed);
+    reload_reactions(&mut simulator);
+
+    // Insert resources
+    commands.insert_resource(DynamicSettings::default());
+    commands.insert_resource(MatterCountHistory::default());
+    commands.insert_resource(GpuTimings::default());
+    commands.insert_resource(ShaderReload::default());
+
+    commands.insert_resource(PreviousMousePos(None));
+    commands.insert_resource(CurrentMousePos(None));
+    commands.insert_resource(InspectedCell(None));
+    commands.insert_resource(Clipboard::default());
+
+    commands.insert_resource(simulator);
+    commands.insert_resource(camera);
+    commands.insert_resource(Minimap::new(canvas_size));
+}
+
+// Creates our render pipelines
+fn setup_render(
+    mut commands: Commands,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    config: Res<Config>,
+) {
+    let (primary_window_renderer, _gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    // Create our render pass
+    let fill_screen = FillScreenRenderPass::new(
+        primary_window_renderer.graphics_queue(),
+        primary_window_renderer.swapchain_format(),
+    );
+    let bloom = BloomPass::new(
+        primary_window_renderer.graphics_queue(),
+        config.canvas_size(),
+    );
+
+    commands.insert_resource(fill_screen);
+    commands.insert_resource(bloom);
+}
+
+// Render the simulation
+fn render(
+    mut vulkano_windows: NonSendMut<BevyVulkanoWindows>,
+    mut fill_screen: ResMut<FillScreenRenderPass>,
+    mut bloom: ResMut<BloomPass>,
+    mut simulator: ResMut<CASimulator>,
+    camera: Res<OrthographicCamera>,
+    minimap: Res<Minimap>,
+    settings: Res<DynamicSettings>,
+) {
+    // Access our window renderer and gui
+    let (window_renderer, gui) = vulkano_windows.get_primary_window_renderer_mut().unwrap();
+    // Start frame
+    let before: Box<dyn GpuFuture> = match window_renderer.acquire() {
+        Err(e) => {
+            bevy::log::error!("Failed to start frame: {}", e);
+            return;
+        }
+        Ok(f) => f,
+    };
+    // The images are sampled after the steps writing them, which may still be running
+    let before = match simulator.after_steps() {
+        Some(after_steps) => before.join(after_steps).boxed(),
+        None => before,
+    };
+    // Blur emissive matter into glow before drawing
+    let (before, glow) = if settings.glow {
+        let after_bloom = bloom.apply(
+            before,
+            simulator.emission_image(),
+            settings.glow_radius,
+            settings.glow_intensity,
+        );
+        let glow: Arc<dyn ImageViewAbstract> = bloom.glow_image();
+        (after_bloom, Some(glow))
+    } else {
+        (before, None)
+    };
+    let canvas_image = simulator.color_image();
+    // Access the final window image (this is the current GPU image which changes between frames)
+    let final_image = window_renderer.swapchain_image_view();
+    let after_images = fill_screen.draw(
+        before,
+        *camera,
+        canvas_image,
+        final_image.clone(),
+        CLEAR_COLOR,
+        false,
+        true,
+        Some(*minimap),
+        glow,
+    );
+    // Draw GUI using egui_winit_window's GUI draw pipeline, also when SandSimGuiPlugin isn't added
+    // so that the host's own egui windows show
+    let after_gui = gui.draw_on_image(after_images, final_image);
+
+    // Finish Frame
+    window_renderer.present(after_gui, true);
+}
+
+// Simulation, painting tools and the CASimulator resource, without drawing anything. Needs
+// VulkanoWinitPlugin with a gui and the input, time and frame time diagnostics plugins.
+// Configured by the Config resource, which is inserted with defaults unless added before this
+// plugin. Its scene is loaded here and sets the canvas size, a scene that fails to load panics.
+// Gameplay systems can read the events in events.rs and spawn Sensor components.
+pub struct SandSimPlugin;
+
+impl Plugin for SandSimPlugin {
+    fn build(&self, app: &mut App) {
+        let mut config = app
+            .world
+            .get_resource_or_insert_with(Config::default)
+            .clone();
+        let scene_library = match config.scene.clone() {
+            Some(path) => {
+                let scene = load_scene(&path)
+                    .and_then(|scene| {
+                        config.resolve_canvas_size(scene.canvas_size)?;
+                        Ok(scene)
+                    })
+                    .unwrap_or_else(|e| panic!("Invalid sand simulation scene: {}", e));
+                SceneLibrary::with_scene(&path, scene)
+            }
+            None => SceneLibrary::default(),
+        };
+        if let Err(e) = config.validate() {
+            panic!("Invalid sand simulation config: {}", e);
+        }
+        app.insert_resource(config.clone())
+            .insert_resource(scene_library)
+            .add_event::<SimulationStepped>()
+            .add_event::<MatterPainted>()
+            .add_event::<MatterEntered>()
+            .add_startup_system(setup)
+            .add_system(input_actions)
+            .add_system(update_camera)
+            .add_system(update_mouse)
+            .add_system(draw_matter)
+            .add_system(place_world_objects)
+            .add_system(inspect_cell)
+            .add_system(select_and_paste)
+            .add_system(handle_scene_requests)
+            .add_system(reload_shaders)
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::new()
+                    .with_run_criteria(FixedTimestep::steps_per_second(config.sim_fps))
+                    .with_system(simulate),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, record_matter_counts)
+            .add_system_to_stage(CoreStage::PostUpdate, emit_sensor_events)
+            .add_system_to_stage(CoreStage::PostUpdate, record_gpu_timings);
+    }
+}
+
+// Draws the canvas, glow, minimap and egui to the primary window and presents it. Leave it out
+// to draw the frame yourself from the CASimulator images. Add after SandSimPlugin.
+pub struct SandSimRenderPlugin;
+
+impl Plugin for SandSimRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_render)
+            .add_system_to_stage(CoreStage::PostUpdate, render);
+    }
+}
+
+// The simulation's egui windows. Add after SandSimPlugin.
+pub struct SandSimGuiPlugin;
+
+impl Plugin for SandSimGuiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(user_interface);
+    }
+}
+
+// Step simulation
+fn simulate(
+    mut sim_pipeline: ResMut<CASimulator>,
+    settings: Res<DynamicSettings>,
+    emitters: Query<&Emitter>,
+    drains: Query<&Drain>,
+    sensors: Query<(Entity, &Sensor)>,
+    mut stepped: EventWriter<SimulationStepped>,
+) {
+    sim_pipeline.set_check_conservation(settings.check_conservation);
+    sim_pipeline.set_view_mode(settings.view_mode);
+    sim_pipeline.set_lighting(settings.lighting);
+    sim_pipeline.set_boundaries(settings.boundaries);
+    sim_pipeline.set_matter_properties(&settings.matter_properties);
+    sim_pipeline.set_world_objects(collect_world_objects(&emitters, &drains));
+    sim_pipeline.set_sensors(collect_sensors(&sensors));
+    let step = sim_pipeline.sim_step();
+    sim_pipeline.step(1, settings.is_paused);
+    if !settings.is_paused {
+        stepped.send(SimulationStepped { step });
+    }
+}
+
+// Update camera (if window is resized)
+fn update_camera(windows: Res<Windows>, mut camera: ResMut<OrthographicCamera>) {
+    let window = windows.get_primary().unwrap();
+    camera.update(window.width(), window.height());
+}
+
+// Input actions for camera movement, zoom and pausing
+fn input_actions(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    mut camera: ResMut<OrthographicCamera>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut mouse_input_events: EventReader<MouseWheel>,
+    mut prev_cursor: Local<Option<Vec2>>,
+    minimap: Res<Minimap>,
+    config: Res<Config>,
+) {
+    let canvas_size = config.canvas_size();
+    let window = windows.get_primary().unwrap();
+    let window_center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+    let cursor = window.cursor_position();
+
+    // Move camera with arrows and WASD
+    let up = keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up);
+    let down = keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down);
+    let left = keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left);
+    let right = keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right);
+
+    let x_axis = -(right as i8) + left as i8;
+    let y_axis = -(up as i8) + down as i8;
+
+    let mut move_delta = Vec2::new(x_axis as f32, y_axis as f32);
+    if move_delta != Vec2::ZERO {
+        move_delta /= move_delta.length();
+        // Move by screen pixels so that panning feels the same at any zoom
+        camera.pan_pixels(move_delta * time.delta_seconds() * CAMERA_MOVE_SPEED);
+    }
+
+    // Pan camera by dragging with middle mouse button
+    if let (Some(cursor), Some(prev_cursor)) = (cursor, *prev_cursor) {
+        if mouse_button_input.pressed(MouseButton::Middle) {
+            camera.pan_pixels(cursor - prev_cursor);
+        }
+    }
+    *prev_cursor = cursor;
+
+    // Zoom camera with mouse scroll towards cursor
+    let zoom_center = cursor.map_or(Vec2::ZERO, |cursor| cursor - window_center);
+    for e in mouse_input_events.iter() {
+        let factor = if e.y < 0.0 {
+            CAMERA_ZOOM_FACTOR
+        } else {
+            1.0 / CAMERA_ZOOM_FACTOR
+        };
+        camera.zoom_around(zoom_center, factor, CAMERA_MIN_SCALE, CAMERA_MAX_SCALE);
+    }
+
+    // Fit whole canvas to window
+    if keyboard_input.just_pressed(KeyCode::F) {
+        camera.pos = Vec2::ZERO;
+        camera.zoom_to_fit_vertical_pixels(canvas_size.y, window.height() as u32);
+    }
+
+    // Jump to the position clicked on minimap
+    if mouse_button_input.pressed(MouseButton::Left) {
+        if let Some(world) = minimap.cursor_to_world(window) {
+            camera.pos = -world;
+        }
+    }
+
+    camera.clamp_to_canvas(canvas_size.as_vec2());
+}
+
+// Mouse position from last frame
+#[derive(Debug, Copy, Clone)]
+pub struct PreviousMousePos(pub Option<MousePos>);
+
+// Mouse position now
+#[derive(Debug, Copy, Clone)]
+pub struct CurrentMousePos(pub Option<MousePos>);
+
+// Update mouse position
+fn update_mouse(
+    windows: Res<Windows>,
+    mut _prev: ResMut<PreviousMousePos>,
+    mut _current: ResMut<CurrentMousePos>,
+    camera: Res<OrthographicCamera>,
+    config: Res<Config>,
+) {
+    _prev.0 = _current.0;
+    let primary = windows.get_primary().unwrap();
+    if primary.cursor_position().is_some() {
+        _current.0 = Some(MousePos::new(
+            cursor_to_world(primary, camera.pos, camera.scale),
+            config.canvas_size(),
+        ));
+    }
+}
+
+//draw to canvas
+
+fn draw_matter(
+    mut simulator: ResMut<CASimulator>,
+    windows: Res<Windows>,
+    prev: Res<PreviousMousePos>,
+    current: Res<CurrentMousePos>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    settings: Res<DynamicSettings>,
+    minimap: Res<Minimap>,
+    mut painted: EventWriter<MatterPainted>,
+) {
+    // Clicks on minimap move the camera instead
+    let over_minimap = minimap
+        .cursor_to_world(windows.get_primary().unwrap())
+        .is_some();
+    if let Some(current) = current.0 {
+        if mouse_button_input.pressed(MouseButton::Left)
+            && !over_minimap
+            && settings.tool == Tool::Brush
+        {
+            let line = get_canvas_line(prev.0, current);
+            // Draw
+            simulator.draw_matter(&line, settings.brush_radius, settings.draw_matter);
+            let bounds = brush_bounds(&line, settings.brush_radius, simulator.canvas_size());
+            if let Some(region) = bounds {
+                painted.send(MatterPainted {
+                    region,
+                    material: settings.draw_matter,
+                });
+            }
+        }
+        if mouse_button_input.pressed(MouseButton::Left)
+            && !over_minimap
+            && settings.tool == Tool::Background
+        {
+            let line = get_canvas_line(prev.0, current);
+            simulator.draw_background(&line, settings.brush_radius, settings.draw_background);
+        }
+        if mouse_button_input.just_pressed(MouseButton::Left)
+            && !over_minimap
+            && settings.tool == Tool::Explode
+        {
+            let canvas_pos = current.canvas_pos().round();
+            simulator.eject(Ejection::explosion(
+                IVec2::new(canvas_pos.x as i32, canvas_pos.y as i32),
+                settings.brush_radius as i32,
+                settings.explosion_strength,
+            ));
+        }
+    }
+}
+
+// Cell under the cursor as (canvas position, matter value), read back from the gpu
+#[derive(Debug, Copy, Clone)]
+pub struct InspectedCell(pub Option<(IVec2, u32)>);
+
+// Request the cell under the cursor and collect the latest one the gpu has read back
+fn inspect_cell(
+    mut simulator: ResMut<CASimulator>,
+    current: Res<CurrentMousePos>,
+    mut inspected: ResMut<InspectedCell>,
+) {
+    if let Some(current) = current.0 {
+        let canvas_pos = current.canvas_pos().round();
+        simulator.request_cell(IVec2::new(canvas_pos.x as i32, canvas_pos.y as i32));
+    }
+    inspected.0 = simulator.poll_cell();
+}
+
+// What left click does on the canvas
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Tool {
+    Brush,
+    // Paint background walls with the brush
+    Background,
+    Emitter,
+    Drain,
+    // Fling matter out of the grid as particles
+    Explode,
+    // Drag a rectangle to copy with ctrl + c
+    Select,
+    // Paste the copied or loaded stamp
+    Paste,
+}
+
+//Drawing settings
+pub struct DynamicSettings {
+    pub tool: Tool,
+    // Expected cells spawned per step by new emitters
+    pub emitter_rate: f32,
+    // Shape of new emitters and drains
+    pub world_object_shape: ShapeKind,
+    // Speed of exploded matter in cells per step
+    pub explosion_strength: f32,
+    pub brush_radius: f32,
+    pub draw_matter: MatterId,
+    pub draw_background: BackgroundId,
+    pub is_paused: bool,
+    pub check_conservation: bool,
+    pub view_mode: ViewMode,
+    pub lighting: LightSettings,
+    pub boundaries: Boundaries,
+    // Properties of each matter id, see matter_properties_table
+    pub matter_properties: Vec<MatterProperties>,
+    pub glow: bool,
+    pub glow_radius: u32,
+    pub glow_intensity: f32,
+}
+
+impl Default for DynamicSettings {
+    fn default() -> Self {
+        Self {
+            tool: Tool::Brush,
+            emitter_rate: 2.0,
+            world_object_shape: ShapeKind::Circle,
+            explosion_strength: 3.0,
+            brush_radius: 4.0,
+            draw_matter: MatterId::Sand,
+            draw_background: BackgroundId::Stone,
+            is_paused: false,
+            check_conservation: false,
+            view_mode: ViewMode::default(),
+            lighting: LightSettings::default(),
+            boundaries: Boundaries::default(),
+            matter_properties: matter_properties_table(),
+            glow: true,
+            glow_radius: 8,
+            glow_intensity: 1.5,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use bytemuck::{Pod, Zeroable};
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 use crate::utils::{u32_rgba_to_u8_rgba, u8_rgba_to_u32_rgba, EMPTY_COLOR};
@@ -10,6 +12,10 @@ pub enum MatterId {
     Rock = 1,
     Sand = 2,
     Water = 3,
+    Lava = 4,
+    Steam = 5,
+    Smoke = 6,
+    Acid = 7,
 }
 
 impl Default for MatterId {
@@ -25,15 +31,92 @@ impl From<u8> for MatterId {
 }
 
 impl MatterId {
+    // Matter with given id, None if the id is not one we simulate
+    pub fn from_id(id: u8) -> Option<MatterId> {
+        MatterId::iter().find(|&matter| matter as u8 == id)
+    }
+
+    // Matter with given name as printed with Debug, e.g. "Sand"
+    pub fn from_name(name: &str) -> Option<MatterId> {
+        MatterId::iter().find(|matter| format!("{:?}", matter) == name)
+    }
+
+    // Character of the matter in text art such as stamp files
+    pub fn symbol(&self) -> char {
+        match *self {
+            MatterId::Empty => '.',
+            MatterId::Rock => '#',
+            MatterId::Sand => 'S',
+            MatterId::Water => 'W',
+            MatterId::Lava => 'L',
+            MatterId::Steam => '~',
+            MatterId::Smoke => '%',
+            MatterId::Acid => 'A',
+        }
+    }
+
+    // Matter with given text art character, see `symbol`
+    pub fn from_symbol(symbol: char) -> Option<MatterId> {
+        MatterId::iter().find(|matter| matter.symbol() == symbol)
+    }
+
     fn color_rgba_u8(&self) -> [u8; 4] {
         let color = match *self {
             MatterId::Empty => EMPTY_COLOR,
             MatterId::Rock => 0xa9a9a9ff,
             MatterId::Sand => 0xc2b280ff,
             MatterId::Water => 0x0000ffff,
+            MatterId::Lava => 0xff4500ff,
+            MatterId::Steam => 0xdcdcdcff,
+            MatterId::Smoke => 0x505050ff,
+            MatterId::Acid => 0x7fff00ff,
         };
         u32_rgba_to_u8_rgba(color)
     }
+
+    pub fn properties(&self) -> MatterProperties {
+        let (flags, opacity, emission) = match *self {
+            MatterId::Empty => (0, 0.0, 0.0),
+            MatterId::Rock => (0, 1.0, 0.0),
+            MatterId::Sand => (MATTER_FLAG_GRAVITY, 0.8, 0.0),
+            MatterId::Water => (MATTER_FLAG_GRAVITY, 0.15, 0.0),
+            MatterId::Lava => (MATTER_FLAG_GRAVITY | MATTER_FLAG_EMISSIVE, 1.0, 1.0),
+            MatterId::Steam => (0, 0.1, 0.0),
+            MatterId::Smoke => (0, 0.5, 0.0),
+            MatterId::Acid => (MATTER_FLAG_GRAVITY, 0.2, 0.0),
+        };
+        MatterProperties {
+            flags,
+            opacity,
+            emission,
+        }
+    }
+}
+
+// Matter glows in the post-processing
+pub const MATTER_FLAG_EMISSIVE: u32 = 1;
+// Matter falls and slides
+pub const MATTER_FLAG_GRAVITY: u32 = 2;
+
+// Per matter properties uploaded to the gpu. Must match MatterProperties in matter.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct MatterProperties {
+    pub flags: u32,
+    // Fraction of light blocked when passing through the matter, 0 to 1
+    pub opacity: f32,
+    // Light given off by the matter, 0 to 1
+    pub emission: f32,
+}
+
+// Properties of every matter id, indexed by the id. Unknown ids get default properties
+pub fn matter_properties_table() -> Vec<MatterProperties> {
+    (0..MATTER_ID_COUNT)
+        .map(|id| {
+            MatterId::from_id(id as u8)
+                .map_or_else(MatterProperties::default, |matter| matter.properties())
+        })
+        .collect()
 }
 
 // Matter data where first 3 bytes are saved for color and last 4th byte is saved for matter identifier
@@ -49,6 +132,23 @@ impl MatterWithColor {
             value: u8_rgba_to_u32_rgba(color[0], color[1], color[2], matter_id as u8),
         }
     }
+
+    pub fn matter_id(&self) -> u8 {
+        (self.value & 255) as u8
+    }
+
+    pub fn color_rgb(&self) -> [u8; 3] {
+        let [r, g, b, _] = u32_rgba_to_u8_rgba(self.value);
+        [r, g, b]
+    }
+}
+
+// Name of a matter id, or the raw id if it's unknown
+pub fn matter_name(matter_id: u8) -> String {
+    match MatterId::from_id(matter_id) {
+        Some(matter) => format!("{:?}", matter),
+        None => format!("Unknown ({})", matter_id),
+    }
 }
 
 impl From<u32> for MatterWithColor {
@@ -56,3 +156,26 @@ impl From<u32> for MatterWithColor {
         Self { value: item }
     }
 }
+
+// Number of distinct matter ids that fit in the matter byte
+pub const MATTER_ID_COUNT: usize = 256;
+
+// Population of each matter id on the canvas after a simulation step
+#[derive(Debug, Clone)]
+pub struct MatterCounts {
+    pub step: u32,
+    pub counts: Vec<u32>,
+}
+
+impl MatterCounts {
+    pub fn new(step: u32, counts: &[u32]) -> MatterCounts {
+        MatterCounts {
+            step,
+            counts: counts.to_vec(),
+        }
+    }
+
+    pub fn get(&self, matter: MatterId) -> u32 {
+        self.counts[matter as usize]
+    }
+}
@@ -0,0 +1,80 @@
+use bevy::prelude::*;
+use vulkano::pipeline::graphics::viewport::Viewport;
+
+use crate::camera::OrthographicCamera;
+
+// Minimap of the whole canvas in the top right corner of the window.
+// All sizes are in physical pixels, so that they match the swapchain image.
+#[derive(Debug, Copy, Clone)]
+pub struct Minimap {
+    canvas_size: Vec2,
+    pub size: f32,
+    pub margin: f32,
+    pub visible: bool,
+}
+
+impl Minimap {
+    pub fn new(canvas_size: UVec2) -> Minimap {
+        Minimap {
+            canvas_size: canvas_size.as_vec2(),
+            size: 200.0,
+            margin: 10.0,
+            visible: true,
+        }
+    }
+
+    // Size of the minimap keeping the aspect ratio of the canvas
+    pub fn dimensions(&self) -> Vec2 {
+        self.canvas_size * (self.size / self.canvas_size.max_element())
+    }
+
+    // Top left corner of the minimap with y down
+    fn origin(&self, window_size: Vec2) -> Vec2 {
+        Vec2::new(
+            window_size.x - self.dimensions().x - self.margin,
+            self.margin,
+        )
+    }
+
+    // Viewport of the minimap for a target of given size
+    pub fn viewport(&self, window_size: Vec2) -> Viewport {
+        Viewport {
+            origin: self.origin(window_size).to_array(),
+            dimensions: self.dimensions().to_array(),
+            depth_range: 0.0..1.0,
+        }
+    }
+
+    // Camera showing the whole canvas inside the minimap viewport
+    pub fn camera(&self) -> OrthographicCamera {
+        let dimensions = self.dimensions();
+        let mut camera = OrthographicCamera::default();
+        camera.update(dimensions.x, dimensions.y);
+        camera.scale = self.canvas_size.x / dimensions.x;
+        camera
+    }
+
+    // World position of a window position (y down), None if it's outside the minimap
+    pub fn window_to_world(&self, window_size: Vec2, window_pos: Vec2) -> Option<Vec2> {
+        if !self.visible {
+            return None;
+        }
+        let dimensions = self.dimensions();
+        let from_origin = window_pos - self.origin(window_size);
+        if from_origin.cmplt(Vec2::ZERO).any() || from_origin.cmpgt(dimensions).any() {
+            return None;
+        }
+        let from_center = from_origin - dimensions / 2.0;
+        Some(Vec2::new(from_center.x, -from_center.y) * self.camera().scale)
+    }
+
+    // World position of the cursor if it's over the minimap
+    pub fn cursor_to_world(&self, window: &Window) -> Option<Vec2> {
+        let cursor = window.physical_cursor_position()?.as_vec2();
+        let window_size = Vec2::new(
+            window.physical_width() as f32,
+            window.physical_height() as f32,
+        );
+        self.window_to_world(window_size, Vec2::new(cursor.x, window_size.y - cursor.y))
+    }
+}
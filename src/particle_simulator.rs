@@ -1,30 +1,48 @@
 //SIMULATION PIPELINE
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
 
 use bevy::{
-    ecs::storage,
-    math::{IVec2, Vec2},
+    ecs::{entity::Entity, storage},
+    math::{IVec2, UVec2, Vec2},
 };
+use bytemuck::{Pod, Zeroable};
+use strum_macros::EnumIter;
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer},
+    buffer::{BufferContents, BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
     command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
-        PrimaryCommandBuffer,
+        AutoCommandBufferBuilder, BufferCopy, CommandBufferUsage, CopyBufferInfoTyped,
+        FillBufferInfo, PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{
+        layout::DescriptorSetLayoutBinding, PersistentDescriptorSet, WriteDescriptorSet,
     },
-    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     device::Queue,
     format::Format,
     image::{ImageUsage, StorageImage},
     pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
-    sync::GpuFuture,
+    shader::ShaderModule,
+    sync::{self, FenceSignalFuture, GpuFuture},
 };
 use vulkano_util::renderer::DeviceImageView;
 
 use crate::{
-    matter::{MatterId, MatterWithColor},
+    background::BackgroundId,
+    matter::{
+        matter_name, matter_properties_table, MatterCounts, MatterId, MatterProperties,
+        MatterWithColor, MATTER_ID_COUNT,
+    },
+    particles::{Ejection, Particle, MAX_PARTICLES},
+    profiler::GpuProfiler,
+    reactions::Reaction,
+    sensors::{SensorCounts, SensorRect},
+    stamps::{PasteMode, Stamp},
     utils::{create_compute_pipeline, storage_buffer_desc, storage_image_desc},
-    CANVAS_SIZE_X, CANVAS_SIZE_Y, LOCAL_SIZE_X, LOCAL_SIZE_Y, NUM_WORK_GROUPS_X, NUM_WORK_GROUPS_Y,
+    world_objects::WorldObject,
+    LOCAL_SIZE_X, LOCAL_SIZE_Y,
 };
 
 // Creates a grid with empty matter values
@@ -42,18 +60,447 @@ fn empty_grid(
     .unwrap()
 }
 
+// Creates a buffer with zero velocity for each cell
+fn empty_velocity(
+    compute_queue: &Arc<Queue>,
+    canvas_size: UVec2,
+) -> Arc<CpuAccessibleBuffer<[f32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        vec![0.0; (canvas_size.x * canvas_size.y) as usize],
+    )
+    .unwrap()
+}
+
+// Creates a buffer with room for every particle
+fn empty_particles(compute_queue: &Arc<Queue>) -> Arc<CpuAccessibleBuffer<[Particle]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        vec![Particle::default(); MAX_PARTICLES],
+    )
+    .unwrap()
+}
+
+// Creates a buffer holding one default element. Lists such as world objects and reactions are
+// bound even when they're empty, and a bound buffer can't be empty.
+fn placeholder_buffer<T>(compute_queue: &Arc<Queue>) -> Arc<CpuAccessibleBuffer<[T]>>
+where
+    T: Default,
+    [T]: BufferContents,
+{
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        vec![T::default()],
+    )
+    .unwrap()
+}
+
+// Creates a dark light buffer holding (sky, emitted) light of each cell
+fn empty_light(
+    compute_queue: &Arc<Queue>,
+    canvas_size: UVec2,
+) -> Arc<CpuAccessibleBuffer<[[f32; 2]]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        vec![[0.0; 2]; (canvas_size.x * canvas_size.y) as usize],
+    )
+    .unwrap()
+}
+
+// Light propagation passes per step. Light travels this many cells per step.
+const LIGHT_PASSES: u32 = 8;
+
+// Steps submitted before waiting for the oldest one, so that the cpu doesn't run ahead of the gpu
+const MAX_STEPS_IN_FLIGHT: usize = 2;
+
+// Fence of a submitted step, shared with work that waits for it, see `after_steps`
+type StepFuture = Arc<FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>>;
+
+// Flags of a paint. Must match paint.glsl
+const PAINT_BACKGROUND: u32 = 1;
+const PAINT_ONLY_EMPTY: u32 = 2;
+
+// Cell write of the painting tools, applied by the paint pass of the next step.
+// Must match Paint in includes.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+struct Paint {
+    index: u32,
+    value: u32,
+    flags: u32,
+}
+
+// Number of readback buffers in flight. Results are read back a few steps later
+// so that reading them never waits for the gpu
+const READBACK_BUFFERS: usize = 3;
+
+// Creates a buffer holding a count for each matter id in each count slot
+fn empty_counts(compute_queue: &Arc<Queue>, slots: usize) -> Arc<CpuAccessibleBuffer<[u32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        true,
+        vec![0; MATTER_ID_COUNT * slots],
+    )
+    .unwrap()
+}
+
+// Counts recorded by a step that have not been read back yet.
+// Slot n holds the population after n checked passes. Passes are only listed (and counted)
+// when conservation checks are on, so the final population is always in the last slot.
+struct PendingCounts {
+    step: u32,
+    buffer_index: usize,
+    checked_passes: Vec<&'static str>,
+}
+
+// Sensor counts recorded by a step that have not been read back yet, with the entity and
+// rectangle of each sensor in buffer order
+struct PendingSensorCounts {
+    step: u32,
+    buffer_index: usize,
+    sensors: Vec<(Entity, SensorRect)>,
+}
+
+// Identifies a grid readback requested with `CASimulator::request_readback`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ReadbackHandle(u64);
+
+// Copy of a rectangle of the matter and background grids, see `CASimulator::poll_readback`
+#[derive(Debug, Clone)]
+pub struct GridReadback {
+    // Step whose result was copied
+    pub step: u32,
+    // Inclusive corners
+    pub min: IVec2,
+    pub max: IVec2,
+    // Cell values of the rectangle, rows bottom to top like the grid
+    pub matter: Vec<u32>,
+    pub background: Vec<u32>,
+}
+
+impl GridReadback {
+    pub fn size(&self) -> UVec2 {
+        (self.max - self.min + IVec2::ONE).as_uvec2()
+    }
+
+    // Matter value at a canvas position, None outside the rectangle
+    pub fn matter_at(&self, pos: IVec2) -> Option<u32> {
+        if pos.cmplt(self.min).any() || pos.cmpgt(self.max).any() {
+            return None;
+        }
+        let offset = pos - self.min;
+        Some(self.matter[(offset.y as u32 * self.size().x + offset.x as u32) as usize])
+    }
+}
+
+// Readback waiting to be recorded by a step, or for the gpu to finish copying
+struct PendingReadback {
+    handle: ReadbackHandle,
+    min: IVec2,
+    max: IVec2,
+    // Step and staging buffers of matter and background, set once recorded
+    recorded: Option<(
+        u32,
+        Arc<CpuAccessibleBuffer<[u32]>>,
+        Arc<CpuAccessibleBuffer<[u32]>>,
+    )>,
+}
+
+// Log every matter whose population changed during a checked pass. Empty cells are not
+// matter, particles leave them behind when ejected and fill them when landing.
+fn log_conservation_errors(step: u32, checked_passes: &[&'static str], counts: &[u32]) {
+    let slots = counts.chunks(MATTER_ID_COUNT).collect::<Vec<_>>();
+    for (pass, &pass_name) in checked_passes.iter().enumerate() {
+        let (before, after) = (slots[pass], slots[pass + 1]);
+        for matter in 1..MATTER_ID_COUNT {
+            if before[matter] != after[matter] {
+                bevy::log::error!(
+                    "Matter not conserved at step {} in {}: {} went from {} to {}",
+                    step,
+                    pass_name,
+                    matter_name(matter as u8),
+                    before[matter],
+                    after[matter]
+                );
+            }
+        }
+    }
+}
+
+// How the color pass visualizes matter. Must match view modes in color.glsl
+#[repr(u32)]
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ViewMode {
+    Normal = 0,
+    MatterId = 1,
+    Moved = 2,
+    Tiles = 3,
+    RawColor = 4,
+}
+
+impl Default for ViewMode {
+    fn default() -> Self {
+        ViewMode::Normal
+    }
+}
+
+// What happens to matter at a canvas edge. Must match boundary modes in includes.glsl
+#[repr(u32)]
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BoundaryMode {
+    // Matter stays inside the canvas
+    Wall = 0,
+    // Matter leaving the canvas is deleted
+    Void = 1,
+    // Matter leaving the canvas enters from the opposite edge
+    Wrap = 2,
+}
+
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Edge {
+    Top = 0,
+    Bottom = 1,
+    Left = 2,
+    Right = 3,
+}
+
+impl Edge {
+    pub fn opposite(&self) -> Edge {
+        match *self {
+            Edge::Top => Edge::Bottom,
+            Edge::Bottom => Edge::Top,
+            Edge::Left => Edge::Right,
+            Edge::Right => Edge::Left,
+        }
+    }
+}
+
+// Boundary mode of each canvas edge
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Boundaries {
+    modes: [BoundaryMode; 4],
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        Boundaries {
+            modes: [BoundaryMode::Wall; 4],
+        }
+    }
+}
+
+impl Boundaries {
+    pub fn get(&self, edge: Edge) -> BoundaryMode {
+        self.modes[edge as usize]
+    }
+
+    // Wrapping only conserves matter when opposite edges wrap together, so setting or
+    // clearing wrap on an edge does the same to its opposite edge
+    pub fn set(&mut self, edge: Edge, mode: BoundaryMode) {
+        let opposite = edge.opposite();
+        if mode == BoundaryMode::Wrap {
+            self.modes[opposite as usize] = BoundaryMode::Wrap;
+        } else if self.get(opposite) == BoundaryMode::Wrap {
+            self.modes[opposite as usize] = mode;
+        }
+        self.modes[edge as usize] = mode;
+    }
+}
+
+// Light shading of the normal view
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightSettings {
+    pub enabled: bool,
+    // Light falling down from the top edge, 0 to 1
+    pub sky_light: f32,
+    // Minimum light of every cell, 0 to 1
+    pub ambient_light: f32,
+    // Fraction of light blocked by background walls, 0 lets light through them
+    pub background_opacity: f32,
+}
+
+impl Default for LightSettings {
+    fn default() -> Self {
+        LightSettings {
+            enabled: true,
+            sky_light: 1.0,
+            ambient_light: 0.1,
+            background_opacity: 0.0,
+        }
+    }
+}
+
+// Work groups covering the canvas, whose size is a multiple of the local size
+pub fn work_groups(canvas_size: UVec2) -> [u32; 3] {
+    [
+        canvas_size.x / LOCAL_SIZE_X,
+        canvas_size.y / LOCAL_SIZE_Y,
+        1,
+    ]
+}
+
+// Work groups with one invocation per particle slot, see get_particle_index in includes.glsl
+fn particle_work_groups() -> [u32; 3] {
+    [MAX_PARTICLES as u32 / (LOCAL_SIZE_X * LOCAL_SIZE_Y), 1, 1]
+}
+
+// This must match the shader and inputs in dispatch
+fn descriptor_layout() -> [(u32, DescriptorSetLayoutBinding); 21] {
+    [
+        (0, storage_buffer_desc()),
+        (1, storage_buffer_desc()),
+        (2, storage_image_desc()),
+        (3, storage_buffer_desc()),
+        (4, storage_buffer_desc()),
+        (5, storage_buffer_desc()),
+        (6, storage_image_desc()),
+        (7, storage_buffer_desc()),
+        (8, storage_buffer_desc()),
+        (9, storage_buffer_desc()),
+        (10, storage_buffer_desc()),
+        (11, storage_buffer_desc()),
+        (12, storage_buffer_desc()),
+        (13, storage_buffer_desc()),
+        (14, storage_buffer_desc()),
+        (15, storage_buffer_desc()),
+        (16, storage_buffer_desc()),
+        (17, storage_buffer_desc()),
+        (18, storage_buffer_desc()),
+        (19, storage_buffer_desc()),
+        (20, storage_buffer_desc()),
+    ]
+}
+
+// Specialization constants shared by every compute shader, see includes.glsl
+fn spec_constants(canvas_size: UVec2) -> color_cs::SpecializationConstants {
+    color_cs::SpecializationConstants {
+        canvas_size_x: canvas_size.x as i32,
+        canvas_size_y: canvas_size.y as i32,
+        empty_matter: 0,
+        constant_3: LOCAL_SIZE_X,
+        constant_4: LOCAL_SIZE_Y,
+    }
+}
+
+// Compute shaders that can be recompiled and swapped while running, see shader_reload.rs
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ReloadableShader {
+    Fall,
+    Slide,
+    Color,
+}
+
+impl ReloadableShader {
+    // Source file in compute_shaders/
+    pub fn file_name(&self) -> &'static str {
+        match *self {
+            ReloadableShader::Fall => "fall_empty.glsl",
+            ReloadableShader::Slide => "slide_down_empty.glsl",
+            ReloadableShader::Color => "color.glsl",
+        }
+    }
+}
+
 // Cellular automata simulation pipeline
 pub struct CASimulator {
     compute_queue: Arc<Queue>,
+    canvas_size: UVec2,
 
     matter_in: Arc<CpuAccessibleBuffer<[u32]>>,
     matter_out: Arc<CpuAccessibleBuffer<[u32]>>,
+    matter_step_start: Arc<CpuAccessibleBuffer<[u32]>>,
+    // Falling velocity of each cell, swapped together with matter
+    velocity_in: Arc<CpuAccessibleBuffer<[f32]>>,
+    velocity_out: Arc<CpuAccessibleBuffer<[f32]>>,
+    // Background walls behind empty cells, see background.rs. Not simulated, so not swapped.
+    background: Arc<CpuAccessibleBuffer<[u32]>>,
     image: DeviceImageView,
+    emission_image: DeviceImageView,
+    matter_properties: Arc<CpuAccessibleBuffer<[MatterProperties]>>,
+    // Properties uploaded at the start of the next step
+    properties_update: Option<Vec<MatterProperties>>,
+    current_properties: Vec<MatterProperties>,
+    view_mode: ViewMode,
+
+    light_in: Arc<CpuAccessibleBuffer<[[f32; 2]]>>,
+    light_out: Arc<CpuAccessibleBuffer<[[f32; 2]]>>,
+    light_settings: LightSettings,
+    boundaries: Boundaries,
+    // Inclusive corners of the rectangle outlined by the color pass
+    selection_outline: Option<(IVec2, IVec2)>,
+
+    world_objects: Vec<WorldObject>,
+    world_objects_buffer: Arc<CpuAccessibleBuffer<[WorldObject]>>,
+
+    // Particles and their count, swapped by the particle pass
+    particles_in: Arc<CpuAccessibleBuffer<[Particle]>>,
+    particles_out: Arc<CpuAccessibleBuffer<[Particle]>>,
+    particle_count_in: Arc<CpuAccessibleBuffer<[u32]>>,
+    particle_count_out: Arc<CpuAccessibleBuffer<[u32]>>,
+    pending_ejections: Vec<Ejection>,
+    // Ejection of the current eject pass
+    ejection: Ejection,
+
+    reaction_count: u32,
+    reactions_buffer: Arc<CpuAccessibleBuffer<[Reaction]>>,
+
+    count_buffers: Vec<Arc<CpuAccessibleBuffer<[u32]>>>,
+    pending_counts: VecDeque<PendingCounts>,
+    latest_counts: Option<MatterCounts>,
+    checked_passes: Vec<&'static str>,
+    check_conservation: bool,
+    count_slot: u32,
+
+    cell_buffers: Vec<Arc<CpuAccessibleBuffer<[u32]>>>,
+    pending_cells: VecDeque<(IVec2, usize)>,
+    requested_cell: Option<IVec2>,
+    latest_cell: Option<(IVec2, u32)>,
+
+    // Grid copies not yet received by their requester
+    pending_readbacks: Vec<PendingReadback>,
+    next_readback: u64,
+
+    // Sensors counted by the next step
+    sensors: Vec<(Entity, SensorRect)>,
+    sensors_buffer: Arc<CpuAccessibleBuffer<[SensorRect]>>,
+    sensor_count_buffers: Vec<Arc<CpuAccessibleBuffer<[u32]>>>,
+    pending_sensor_counts: VecDeque<PendingSensorCounts>,
+
+    // Painting and loaded grids are applied at the start of the next step, since the gpu may
+    // still be using the grids. Paints are keyed by cell index and background flag.
+    pending_paints: HashMap<(u32, u32), Paint>,
+    pending_grids: Option<(Vec<u32>, Vec<u32>)>,
+    paint_count: u32,
+    paints_buffer: Arc<CpuAccessibleBuffer<[Paint]>>,
+
+    // Submitted steps the gpu may not have finished, oldest first
+    steps_in_flight: VecDeque<StepFuture>,
 
     color_pipeline: Arc<ComputePipeline>,
     fall_pipeline: Arc<ComputePipeline>,
     slide_pipeline: Arc<ComputePipeline>,
+    count_pipeline: Arc<ComputePipeline>,
+    light_pipeline: Arc<ComputePipeline>,
+    world_objects_pipeline: Arc<ComputePipeline>,
+    eject_pipeline: Arc<ComputePipeline>,
+    particle_pipeline: Arc<ComputePipeline>,
+    particle_draw_pipeline: Arc<ComputePipeline>,
+    react_pipeline: Arc<ComputePipeline>,
+    sensor_pipeline: Arc<ComputePipeline>,
+    paint_pipeline: Arc<ComputePipeline>,
 
+    profiler: Option<GpuProfiler>,
+
+    seed: u32,
     sim_step: u32,
     move_step: u32,
 }
@@ -78,41 +525,111 @@ mod slide_empty_cs {
         path: "compute_shaders/slide_down_empty.glsl"
     }
 }
+mod count_matter_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/count_matter.glsl"
+    }
+}
+mod light_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/light.glsl"
+    }
+}
+mod world_objects_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/world_objects.glsl"
+    }
+}
+mod eject_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/eject.glsl"
+    }
+}
+mod particles_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/particles.glsl"
+    }
+}
+mod particle_draw_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/particle_draw.glsl"
+    }
+}
+mod react_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/react.glsl"
+    }
+}
+mod sensors_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/sensors.glsl"
+    }
+}
+mod paint_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/paint.glsl"
+    }
+}
 
 //------------------
 
 impl CASimulator {
     // Create new simulator pipeline for a compute queue.
     // Ensure that canvas sizes are divisible by kernel sizes so no pixel
-    // remains unsimulated.
-    pub fn new(compute_queue: Arc<Queue>) -> CASimulator {
+    // remains unsimulated, see Config::validate.
+    pub fn new(compute_queue: Arc<Queue>, canvas_size: UVec2) -> CASimulator {
         // In order to not miss any pixels, the following must be true
-        assert_eq!(CANVAS_SIZE_X % LOCAL_SIZE_X, 0);
-        assert_eq!(CANVAS_SIZE_Y % LOCAL_SIZE_Y, 0);
-        let matter_in = empty_grid(&compute_queue, CANVAS_SIZE_X, CANVAS_SIZE_Y);
-        let matter_out = empty_grid(&compute_queue, CANVAS_SIZE_X, CANVAS_SIZE_Y);
-
-        let spec_const = color_cs::SpecializationConstants {
-            canvas_size_x: CANVAS_SIZE_X as i32,
-            canvas_size_y: CANVAS_SIZE_Y as i32,
-            empty_matter: 0,
-            constant_3: LOCAL_SIZE_X,
-            constant_4: LOCAL_SIZE_Y,
-        };
+        assert_eq!(canvas_size.x % LOCAL_SIZE_X, 0);
+        assert_eq!(canvas_size.y % LOCAL_SIZE_Y, 0);
+        let matter_in = empty_grid(&compute_queue, canvas_size.x, canvas_size.y);
+        let matter_out = empty_grid(&compute_queue, canvas_size.x, canvas_size.y);
+        let matter_step_start = empty_grid(&compute_queue, canvas_size.x, canvas_size.y);
+        let velocity_in = empty_velocity(&compute_queue, canvas_size);
+        let velocity_out = empty_velocity(&compute_queue, canvas_size);
+        let background = empty_grid(&compute_queue, canvas_size.x, canvas_size.y);
+
+        let spec_const = spec_constants(canvas_size);
 
         // Create pipelines
-        let (fall_pipeline, color_pipeline, slide_pipeline) = {
+        let (
+            fall_pipeline,
+            color_pipeline,
+            slide_pipeline,
+            count_pipeline,
+            light_pipeline,
+            world_objects_pipeline,
+            eject_pipeline,
+            particle_pipeline,
+            particle_draw_pipeline,
+            react_pipeline,
+            sensor_pipeline,
+            paint_pipeline,
+        ) = {
             let fall_shader = fall_empty_cs::load(compute_queue.device().clone()).unwrap();
             let color_shader = color_cs::load(compute_queue.device().clone()).unwrap();
             let slide_shader = slide_empty_cs::load(compute_queue.device().clone()).unwrap();
+            let count_shader = count_matter_cs::load(compute_queue.device().clone()).unwrap();
+            let light_shader = light_cs::load(compute_queue.device().clone()).unwrap();
+            let world_objects_shader =
+                world_objects_cs::load(compute_queue.device().clone()).unwrap();
+            let eject_shader = eject_cs::load(compute_queue.device().clone()).unwrap();
+            let particles_shader = particles_cs::load(compute_queue.device().clone()).unwrap();
+            let particle_draw_shader =
+                particle_draw_cs::load(compute_queue.device().clone()).unwrap();
+            let react_shader = react_cs::load(compute_queue.device().clone()).unwrap();
+            let sensors_shader = sensors_cs::load(compute_queue.device().clone()).unwrap();
+            let paint_shader = paint_cs::load(compute_queue.device().clone()).unwrap();
+            let descriptor_layout = descriptor_layout();
 
-            // This must match the shader and inputs in dispatch
-            let descriptor_layout = [
-                (0, storage_buffer_desc()),
-                (1, storage_buffer_desc()),
-                (2, storage_image_desc()),
-                (3, storage_image_desc()),
-            ];
             (
                 create_compute_pipeline(
                     compute_queue.clone(),
@@ -132,13 +649,67 @@ impl CASimulator {
                     descriptor_layout.to_vec(),
                     &spec_const,
                 ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    count_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    light_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    world_objects_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    eject_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    particles_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    particle_draw_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    react_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    sensors_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    paint_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
             )
         };
 
         // Create color image
         let image = StorageImage::general_purpose_image_view(
             compute_queue.clone(),
-            [CANVAS_SIZE_X, CANVAS_SIZE_Y],
+            canvas_size.to_array(),
             Format::R8G8B8A8_UNORM,
             ImageUsage {
                 sampled: true,
@@ -148,36 +719,511 @@ impl CASimulator {
             },
         )
         .unwrap();
+        // Create emission image, input of glow post-processing
+        let emission_image = StorageImage::general_purpose_image_view(
+            compute_queue.clone(),
+            canvas_size.to_array(),
+            Format::R16G16B16A16_SFLOAT,
+            ImageUsage {
+                sampled: true,
+                storage: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
+        let current_properties = matter_properties_table();
+        let matter_properties = CpuAccessibleBuffer::from_iter(
+            compute_queue.device().clone(),
+            BufferUsage::all(),
+            false,
+            current_properties.clone(),
+        )
+        .unwrap();
+        let light_in = empty_light(&compute_queue, canvas_size);
+        let light_out = empty_light(&compute_queue, canvas_size);
+        let world_objects_buffer = placeholder_buffer::<WorldObject>(&compute_queue);
+        let reactions_buffer = placeholder_buffer::<Reaction>(&compute_queue);
+        let sensors_buffer = placeholder_buffer::<SensorRect>(&compute_queue);
+        let paints_buffer = placeholder_buffer::<Paint>(&compute_queue);
+        let particles_in = empty_particles(&compute_queue);
+        let particles_out = empty_particles(&compute_queue);
+        let particle_count_in = empty_grid(&compute_queue, 1, 1);
+        let particle_count_out = empty_grid(&compute_queue, 1, 1);
+        let profiler = GpuProfiler::new(&compute_queue);
+        let count_buffers = (0..READBACK_BUFFERS)
+            .map(|_| empty_counts(&compute_queue, 1))
+            .collect();
+        let cell_buffers = (0..READBACK_BUFFERS)
+            .map(|_| empty_grid(&compute_queue, 1, 1))
+            .collect();
+        let sensor_count_buffers = (0..READBACK_BUFFERS)
+            .map(|_| empty_counts(&compute_queue, 1))
+            .collect();
         CASimulator {
             compute_queue,
+            canvas_size,
             matter_in,
             matter_out,
+            matter_step_start,
+            velocity_in,
+            velocity_out,
+            background,
             image,
+            emission_image,
+            matter_properties,
+            properties_update: None,
+            current_properties,
+            view_mode: ViewMode::default(),
+            light_in,
+            light_out,
+            light_settings: LightSettings::default(),
+            boundaries: Boundaries::default(),
+            selection_outline: None,
+            world_objects: vec![],
+            world_objects_buffer,
+            particles_in,
+            particles_out,
+            particle_count_in,
+            particle_count_out,
+            pending_ejections: vec![],
+            ejection: Ejection::default(),
+            reaction_count: 0,
+            reactions_buffer,
+            count_buffers,
+            pending_counts: VecDeque::new(),
+            latest_counts: None,
+            checked_passes: vec![],
+            check_conservation: false,
+            count_slot: 0,
+            cell_buffers,
+            pending_cells: VecDeque::new(),
+            requested_cell: None,
+            latest_cell: None,
+            pending_readbacks: vec![],
+            next_readback: 0,
+            sensors: vec![],
+            sensors_buffer,
+            sensor_count_buffers,
+            pending_sensor_counts: VecDeque::new(),
+            pending_paints: HashMap::new(),
+            pending_grids: None,
+            paint_count: 0,
+            paints_buffer,
+            steps_in_flight: VecDeque::new(),
             color_pipeline,
             fall_pipeline,
             slide_pipeline,
+            count_pipeline,
+            light_pipeline,
+            world_objects_pipeline,
+            eject_pipeline,
+            particle_pipeline,
+            particle_draw_pipeline,
+            react_pipeline,
+            sensor_pipeline,
+            paint_pipeline,
+            profiler,
+            seed: 0,
             sim_step: 0,
             move_step: 0,
         }
     }
 
+    // Replace the pipeline of a shader with one built from runtime compiled spir-v. The new
+    // pipeline reuses the layout of the old one, which dispatch pushes the build time push
+    // constants to, so shaders with other bindings or push constants are rejected and the old
+    // pipeline is kept.
+    pub fn reload_shader(&mut self, shader: ReloadableShader, spirv: &[u32]) -> Result<(), String> {
+        let device = self.compute_queue.device().clone();
+        // Safety: spir-v comes from shaderc and is validated against the layout below
+        let module = unsafe { ShaderModule::from_words(device.clone(), spirv) }
+            .map_err(|e| e.to_string())?;
+        let entry_point = module
+            .entry_point("main")
+            .ok_or_else(|| "Shader has no main entry point".to_string())?;
+        let layout = match shader {
+            ReloadableShader::Fall => self.fall_pipeline.layout(),
+            ReloadableShader::Slide => self.slide_pipeline.layout(),
+            ReloadableShader::Color => self.color_pipeline.layout(),
+        }
+        .clone();
+        let pipeline = ComputePipeline::with_pipeline_layout(
+            device,
+            entry_point,
+            &spec_constants(self.canvas_size),
+            layout,
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+        match shader {
+            ReloadableShader::Fall => self.fall_pipeline = pipeline,
+            ReloadableShader::Slide => self.slide_pipeline = pipeline,
+            ReloadableShader::Color => self.color_pipeline = pipeline,
+        }
+        Ok(())
+    }
+
+    pub fn canvas_size(&self) -> UVec2 {
+        self.canvas_size
+    }
+
+    // Number of the next step
+    pub fn sim_step(&self) -> u32 {
+        self.sim_step
+    }
+
+    // Block until the gpu has finished all submitted steps
+    pub fn wait_idle(&mut self) {
+        for step in self.steps_in_flight.drain(..) {
+            step.wait(None).unwrap();
+        }
+    }
+
+    // Future of the steps still running, for work on other queues that uses the images or
+    // buffers of the simulation, e.g. rendering. None once the gpu has finished every step.
+    pub fn after_steps(&mut self) -> Option<Box<dyn GpuFuture>> {
+        self.cleanup_finished_steps();
+        let latest = self.steps_in_flight.back()?.clone();
+        Some(latest.then_signal_semaphore_and_flush().unwrap().boxed())
+    }
+
+    // Release the buffers of steps the gpu has finished, so that their results can be read
+    fn cleanup_finished_steps(&mut self) {
+        while let Some(step) = self.steps_in_flight.front() {
+            if !step.is_signaled().unwrap() {
+                break;
+            }
+            // Doesn't block, the fence is signaled
+            step.wait(None).unwrap();
+            self.steps_in_flight.pop_front();
+        }
+    }
+
+    // Salt of the random numbers of the simulation, e.g. for reproducible runs
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
     // Get canvas image for rendering
     pub fn color_image(&self) -> DeviceImageView {
         self.image.clone()
     }
 
+    // Get image of emissive matter for glow post-processing
+    pub fn emission_image(&self) -> DeviceImageView {
+        self.emission_image.clone()
+    }
+
+    // Read back population counts of steps the gpu has finished and return the latest ones.
+    // This never waits for the gpu, counts still in flight are read on a later call.
+    // Conservation errors are logged here too.
+    pub fn poll_matter_counts(&mut self) -> Option<&MatterCounts> {
+        self.cleanup_finished_steps();
+        while let Some(pending) = self.pending_counts.front() {
+            let buffer = self.count_buffers[pending.buffer_index].clone();
+            let counts = match buffer.read() {
+                Ok(counts) => counts,
+                Err(_) => break,
+            };
+            log_conservation_errors(pending.step, &pending.checked_passes, &counts);
+            let final_slot = pending.checked_passes.len() * MATTER_ID_COUNT;
+            self.latest_counts = Some(MatterCounts::new(
+                pending.step,
+                &counts[final_slot..final_slot + MATTER_ID_COUNT],
+            ));
+            self.pending_counts.pop_front();
+        }
+        self.latest_counts.as_ref()
+    }
+
+    // Verify that movement passes neither create nor destroy matter. Costs an extra counting
+    // pass per movement pass while enabled.
+    pub fn set_check_conservation(&mut self, check_conservation: bool) {
+        self.check_conservation = check_conservation;
+    }
+
+    pub fn set_view_mode(&mut self, view_mode: ViewMode) {
+        self.view_mode = view_mode;
+    }
+
+    pub fn set_lighting(&mut self, light_settings: LightSettings) {
+        self.light_settings = light_settings;
+    }
+
+    // Emitters and drains applied at the start of each step
+    pub fn set_world_objects(&mut self, world_objects: Vec<WorldObject>) {
+        self.world_objects = world_objects;
+    }
+
+    // Replace the reaction table. Matter reacts from the next step on.
+    pub fn set_reactions(&mut self, reactions: &[Reaction]) {
+        self.reaction_count = reactions.len() as u32;
+        if !reactions.is_empty() {
+            self.reactions_buffer = CpuAccessibleBuffer::from_iter(
+                self.compute_queue.device().clone(),
+                BufferUsage::all(),
+                false,
+                reactions.to_vec(),
+            )
+            .unwrap();
+        }
+    }
+
+    // Turn matter into particles on the next unpaused step
+    pub fn eject(&mut self, ejection: Ejection) {
+        self.pending_ejections.push(ejection);
+    }
+
+    // Void edges delete matter, which conservation checks report as errors
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+    }
+
+    // Replace the properties of every matter id. Changes are uploaded at the start of the
+    // next step.
+    pub fn set_matter_properties(&mut self, properties: &[MatterProperties]) {
+        if self.current_properties != properties {
+            self.current_properties = properties.to_vec();
+            self.properties_update = Some(properties.to_vec());
+        }
+    }
+
+    // Read back the value of a cell after the next step, see `poll_cell`
+    pub fn request_cell(&mut self, pos: IVec2) {
+        if self.is_inside(pos) {
+            self.requested_cell = Some(pos);
+        }
+    }
+
+    // Latest requested cell the gpu has finished as (position, matter value).
+    // This never waits for the gpu.
+    pub fn poll_cell(&mut self) -> Option<(IVec2, u32)> {
+        self.cleanup_finished_steps();
+        while let Some(&(pos, buffer_index)) = self.pending_cells.front() {
+            match self.cell_buffers[buffer_index].read() {
+                Ok(cell) => self.latest_cell = Some((pos, cell[0])),
+                Err(_) => break,
+            }
+            self.pending_cells.pop_front();
+        }
+        self.latest_cell
+    }
+
+    // Copy a rectangle of the grids with inclusive corners at the end of the next step. The
+    // rectangle is clamped to the canvas, None if it's entirely outside. Any number of
+    // readbacks may be in flight, see `poll_readback`.
+    pub fn request_readback(&mut self, min: IVec2, max: IVec2) -> Option<ReadbackHandle> {
+        let min = min.max(IVec2::ZERO);
+        let max = max.min(self.canvas_size.as_ivec2() - IVec2::ONE);
+        if min.cmpgt(max).any() {
+            return None;
+        }
+        let handle = ReadbackHandle(self.next_readback);
+        self.next_readback += 1;
+        self.pending_readbacks.push(PendingReadback {
+            handle,
+            min,
+            max,
+            recorded: None,
+        });
+        Some(handle)
+    }
+
+    // Copy of the whole grids at the end of the next step, see `request_readback`
+    pub fn request_grid_readback(&mut self) -> ReadbackHandle {
+        let max = self.canvas_size.as_ivec2() - IVec2::ONE;
+        self.request_readback(IVec2::ZERO, max).unwrap()
+    }
+
+    // Take a readback once the gpu has finished copying it. Returns None while it's in flight
+    // and for unknown or already taken handles. This never waits for the gpu.
+    pub fn poll_readback(&mut self, handle: ReadbackHandle) -> Option<GridReadback> {
+        self.cleanup_finished_steps();
+        let index = self
+            .pending_readbacks
+            .iter()
+            .position(|pending| pending.handle == handle)?;
+        let (matter, background) = {
+            let (_, matter, background) = self.pending_readbacks[index].recorded.as_ref()?;
+            // Locked while the gpu still uses the buffers
+            let matter = matter.read().ok()?.to_vec();
+            let background = background.read().ok()?.to_vec();
+            (matter, background)
+        };
+        let pending = self.pending_readbacks.remove(index);
+        Some(GridReadback {
+            step: pending.recorded.map_or(0, |(step, _, _)| step),
+            min: pending.min,
+            max: pending.max,
+            matter,
+            background,
+        })
+    }
+
+    // Forget a readback that is no longer wanted
+    pub fn cancel_readback(&mut self, handle: ReadbackHandle) {
+        self.pending_readbacks
+            .retain(|pending| pending.handle != handle);
+    }
+
+    // Rectangles whose matter is counted by each unpaused step, see `poll_sensor_counts`
+    pub fn set_sensors(&mut self, sensors: Vec<(Entity, SensorRect)>) {
+        self.sensors = sensors;
+    }
+
+    // Sensor counts of steps the gpu has finished since the last call, oldest first.
+    // This never waits for the gpu.
+    pub fn poll_sensor_counts(&mut self) -> Vec<SensorCounts> {
+        self.cleanup_finished_steps();
+        let mut finished = vec![];
+        while let Some(pending) = self.pending_sensor_counts.front() {
+            let buffer = self.sensor_count_buffers[pending.buffer_index].clone();
+            let counts = match buffer.read() {
+                Ok(counts) => counts,
+                Err(_) => break,
+            };
+            finished.extend(
+                pending
+                    .sensors
+                    .iter()
+                    .zip(counts.chunks(MATTER_ID_COUNT))
+                    .map(|(&(sensor, rect), counts)| SensorCounts {
+                        step: pending.step,
+                        sensor,
+                        rect,
+                        counts: counts.to_vec(),
+                    }),
+            );
+            self.pending_sensor_counts.pop_front();
+        }
+        finished
+    }
+
+    // Gpu milliseconds of each pass in steps finished since the last call
+    pub fn take_gpu_timings(&mut self) -> Vec<(&'static str, f32)> {
+        self.profiler
+            .as_mut()
+            .map_or_else(Vec::new, |profiler| profiler.take_timings())
+    }
+
+    // Readback buffers written by the current step
+    fn readback_index(&self) -> usize {
+        self.sim_step as usize % READBACK_BUFFERS
+    }
+
     fn is_inside(&self, pos: IVec2) -> bool {
-        pos.x >= 0 && pos.x < CANVAS_SIZE_X as i32 && pos.y >= 0 && pos.y < CANVAS_SIZE_Y as i32
+        pos.cmpge(IVec2::ZERO).all() && pos.cmplt(self.canvas_size.as_ivec2()).all()
     }
 
     // Index to access our one dimensional grid with two dimensional position
     fn index(&self, pos: IVec2) -> usize {
-        (pos.y * CANVAS_SIZE_Y as i32 + pos.x) as usize
+        (pos.y * self.canvas_size.x as i32 + pos.x) as usize
     }
 
-    // Draw matter line with given radius
+    // Queue a cell write for the paint pass of the next step. When a cell is painted twice
+    // before a step, a later paint only into empty cells keeps an earlier non-empty one.
+    fn queue_paint(&mut self, index: usize, value: u32, flags: u32) {
+        let paint = Paint {
+            index: index as u32,
+            value,
+            flags,
+        };
+        let key = (paint.index, flags & PAINT_BACKGROUND);
+        let paint = match self.pending_paints.get(&key) {
+            Some(&earlier) if flags & PAINT_ONLY_EMPTY != 0 => {
+                if MatterWithColor::from(earlier.value).matter_id() != 0 {
+                    earlier
+                } else {
+                    // Applies only where the earlier paint did
+                    Paint {
+                        flags: earlier.flags,
+                        ..paint
+                    }
+                }
+            }
+            _ => paint,
+        };
+        self.pending_paints.insert(key, paint);
+    }
+
+    // Draw matter line with given radius, applied by the next step
     pub fn draw_matter(&mut self, line: &[IVec2], radius: f32, matter: MatterId) {
-        let mut matter_in = self.matter_in.write().unwrap();
+        let value = MatterWithColor::new(matter).value;
+        for index in self.brush_indices(line, radius) {
+            self.queue_paint(index, value, 0);
+        }
+    }
+
+    // Copy of the whole matter and background grids, e.g. for saving scenes. Waits for the
+    // gpu, painting not yet applied by a step is missing.
+    pub fn read_grids(&mut self) -> (Vec<u32>, Vec<u32>) {
+        self.wait_idle();
+        (
+            self.matter_in.read().unwrap().to_vec(),
+            self.background.read().unwrap().to_vec(),
+        )
+    }
+
+    // Replace the matter and background grids at the start of the next step, discarding
+    // painting queued before. Everything moving is stopped and particles in flight are dropped.
+    pub fn load_grids(&mut self, matter: &[u32], background: &[u32]) {
+        self.pending_grids = Some((matter.to_vec(), background.to_vec()));
+        self.pending_paints.clear();
+        self.pending_ejections.clear();
+    }
+
+    // Copy the matter values of a rectangle with inclusive corners. Cells outside the canvas
+    // are empty. Waits for the gpu like `read_grids`.
+    pub fn copy_region(&mut self, min: IVec2, max: IVec2) -> Stamp {
+        self.wait_idle();
+        let matter_in = self.matter_in.read().unwrap();
+        let size = max - min + IVec2::ONE;
+        let cells = (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .map(|pos| {
+                if self.is_inside(pos) {
+                    matter_in[self.index(pos)]
+                } else {
+                    0
+                }
+            })
+            .collect();
+        Stamp::new(size.x as u32, size.y as u32, cells)
+    }
+
+    // Write a stamp with its lower left corner at origin, applied by the next step.
+    // Overwriting replaces the whole rectangle, including with the stamp's empty cells.
+    pub fn paste_stamp(&mut self, origin: IVec2, stamp: &Stamp, mode: PasteMode) {
+        let flags = match mode {
+            PasteMode::OnlyEmpty => PAINT_ONLY_EMPTY,
+            PasteMode::Overwrite => 0,
+        };
+        for y in 0..stamp.height {
+            for x in 0..stamp.width {
+                let pos = origin + IVec2::new(x as i32, y as i32);
+                if self.is_inside(pos) {
+                    self.queue_paint(self.index(pos), stamp.get(x, y), flags);
+                }
+            }
+        }
+    }
+
+    // Outline a rectangle with inclusive corners on the canvas, e.g. the current selection
+    pub fn set_selection_outline(&mut self, outline: Option<(IVec2, IVec2)>) {
+        self.selection_outline = outline;
+    }
+
+    // Paint background walls along a line, BackgroundId::Empty erases them. Applied by the
+    // next step.
+    pub fn draw_background(&mut self, line: &[IVec2], radius: f32, background: BackgroundId) {
+        for index in self.brush_indices(line, radius) {
+            self.queue_paint(index, background.value(), PAINT_BACKGROUND);
+        }
+    }
+
+    // Indices of the cells covered by a circle brush moved along a line
+    fn brush_indices(&self, line: &[IVec2], radius: f32) -> Vec<usize> {
+        let mut indices = vec![];
         for &pos in line.iter() {
             if !self.is_inside(pos) {
                 continue;
@@ -195,33 +1241,124 @@ impl CASimulator {
                         <= radius
                     {
                         if self.is_inside([x, y].into()) {
-                            // Draw
-                            matter_in[self.index([x, y].into())] =
-                                MatterWithColor::new(matter).value;
+                            indices.push(self.index([x, y].into()));
                         }
                     }
                 }
             }
         }
+        indices
     }
 
     //--------------------------------------------------
     //DISPLAY DRAWING
 
-    // Step simulation
+    // Step simulation. Doesn't wait for the gpu unless MAX_STEPS_IN_FLIGHT steps are running.
     pub fn step(&mut self, move_steps: u32, is_paused: bool) {
+        while self.steps_in_flight.len() >= MAX_STEPS_IN_FLIGHT {
+            self.steps_in_flight
+                .pop_front()
+                .unwrap()
+                .wait(None)
+                .unwrap();
+        }
+        self.cleanup_finished_steps();
+
         let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
             self.compute_queue.device().clone(),
             self.compute_queue.family(),
             CommandBufferUsage::OneTimeSubmit,
         )
         .unwrap();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame(&mut command_buffer_builder);
+        }
+
+        // Properties are copied in the command buffer, since the gpu may still be reading them
+        if let Some(properties) = self.properties_update.take() {
+            let staging = CpuAccessibleBuffer::from_iter(
+                self.compute_queue.device().clone(),
+                BufferUsage::transfer_src(),
+                false,
+                properties,
+            )
+            .unwrap();
+            command_buffer_builder
+                .copy_buffer(CopyBufferInfoTyped::buffers(
+                    staging,
+                    self.matter_properties.clone(),
+                ))
+                .unwrap();
+        }
+
+        //this applies loaded grids and painting
+        self.upload_grids(&mut command_buffer_builder);
+        self.apply_paints(&mut command_buffer_builder);
+
+        // Moved cells view compares the colored state with the state at the start of the step
+        if self.view_mode == ViewMode::Moved {
+            command_buffer_builder
+                .copy_buffer(CopyBufferInfoTyped::buffers(
+                    self.matter_in.clone(),
+                    self.matter_step_start.clone(),
+                ))
+                .unwrap();
+        }
+
+        //this spawns and deletes matter of emitters and drains, and lets matter react
+        if !is_paused {
+            self.apply_world_objects(&mut command_buffer_builder);
+            if self.reaction_count > 0 {
+                self.dispatch(
+                    &mut command_buffer_builder,
+                    self.react_pipeline.clone(),
+                    true,
+                    "react_pipeline",
+                );
+            }
+        }
+
+        // Movement passes, ejections and the particle pass are checked for conservation
+        let checked_passes = if is_paused {
+            0
+        } else {
+            2 * move_steps + self.pending_ejections.len() as u32 + 1
+        };
+        self.begin_matter_counts(&mut command_buffer_builder, checked_passes);
 
         //this dispatches the movement compute shaders
         if !is_paused {
             for _ in 0..move_steps {
-                self.step_movement(&mut command_buffer_builder, self.fall_pipeline.clone());
-                self.step_movement(&mut command_buffer_builder, self.slide_pipeline.clone());
+                self.step_movement(
+                    &mut command_buffer_builder,
+                    self.fall_pipeline.clone(),
+                    "fall_pipeline",
+                );
+                self.step_movement(
+                    &mut command_buffer_builder,
+                    self.slide_pipeline.clone(),
+                    "slide_pipeline",
+                );
+            }
+
+            //this moves matter between the grid and free particles
+            self.step_particles(&mut command_buffer_builder);
+        }
+
+        //this counts the population of each matter so it can be read back later
+        self.finish_matter_counts(&mut command_buffer_builder);
+        if !is_paused {
+            self.record_sensor_counts(&mut command_buffer_builder);
+        }
+
+        //this copies the requested cell and grid rectangles so they can be read back later
+        self.record_cell_readback(&mut command_buffer_builder);
+        self.record_grid_readbacks(&mut command_buffer_builder);
+
+        //this spreads light from the sky and emissive matter
+        if self.light_settings.enabled {
+            for _ in 0..LIGHT_PASSES {
+                self.step_light(&mut command_buffer_builder);
             }
         }
 
@@ -231,21 +1368,276 @@ impl CASimulator {
             &mut command_buffer_builder,
             self.color_pipeline.clone(),
             false,
+            "color_pipeline",
+        );
+        self.dispatch_groups(
+            &mut command_buffer_builder,
+            self.particle_draw_pipeline.clone(),
+            false,
+            "particle_draw_pipeline",
+            particle_work_groups(),
         );
 
+        // Chained after the previous step, which may still be running
         let command_buffer = command_buffer_builder.build().unwrap();
-        let finished = command_buffer.execute(self.compute_queue.clone()).unwrap();
-        let _fut = finished.then_signal_fence_and_flush().unwrap();
+        let previous = match self.steps_in_flight.back() {
+            Some(step) => step.clone().boxed_send_sync(),
+            None => sync::now(self.compute_queue.device().clone()).boxed_send_sync(),
+        };
+        let finished = previous
+            .then_execute(self.compute_queue.clone(), command_buffer)
+            .unwrap()
+            .boxed_send_sync()
+            .then_signal_fence_and_flush()
+            .unwrap();
+        self.steps_in_flight.push_back(Arc::new(finished));
 
         self.sim_step += 1;
     }
 
-    // Append a pipeline dispatch to our command buffer
+    // Copy grids queued by `load_grids` over matter_in and background, and stop everything
+    // moving
+    fn upload_grids(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let (matter, background) = match self.pending_grids.take() {
+            Some(grids) => grids,
+            None => return,
+        };
+        for (grid, destination) in [
+            (matter, self.matter_in.clone()),
+            (background, self.background.clone()),
+        ] {
+            let staging = CpuAccessibleBuffer::from_iter(
+                self.compute_queue.device().clone(),
+                BufferUsage::transfer_src(),
+                false,
+                grid,
+            )
+            .unwrap();
+            builder
+                .copy_buffer(CopyBufferInfoTyped::buffers(staging, destination))
+                .unwrap();
+        }
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(self.velocity_in.clone()))
+            .unwrap();
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(self.particle_count_in.clone()))
+            .unwrap();
+    }
+
+    // Upload queued paints and append a pass writing them to the grids
+    fn apply_paints(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        if self.pending_paints.is_empty() {
+            return;
+        }
+        let paints = std::mem::take(&mut self.pending_paints);
+        self.paint_count = paints.len() as u32;
+        self.paints_buffer = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::all(),
+            false,
+            paints.into_values(),
+        )
+        .unwrap();
+        self.dispatch(
+            builder,
+            self.paint_pipeline.clone(),
+            false,
+            "paint_pipeline",
+        );
+    }
+
+    // Clear this step's count buffer. With conservation checks on, also count the population
+    // before the first checked pass.
+    fn begin_matter_counts(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        checked_passes: u32,
+    ) {
+        let buffer_index = self.readback_index();
+        // Counts not read back by now are overwritten
+        self.pending_counts
+            .retain(|pending| pending.buffer_index != buffer_index);
+
+        let slots = if self.check_conservation {
+            checked_passes as usize + 1
+        } else {
+            1
+        };
+        if self.count_buffers[buffer_index].len() < (MATTER_ID_COUNT * slots) as u64 {
+            self.count_buffers[buffer_index] = empty_counts(&self.compute_queue, slots);
+        }
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(
+                self.count_buffers[buffer_index].clone(),
+            ))
+            .unwrap();
+
+        self.checked_passes.clear();
+        if self.check_conservation {
+            self.dispatch_count(builder, 0);
+        }
+    }
+
+    // Count the final population unless the last checked pass already did, and queue the
+    // counts for reading back
+    fn finish_matter_counts(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        if !self.check_conservation {
+            self.dispatch_count(builder, 0);
+        }
+        self.pending_counts.push_back(PendingCounts {
+            step: self.sim_step,
+            buffer_index: self.readback_index(),
+            checked_passes: std::mem::take(&mut self.checked_passes),
+        });
+    }
+
+    // Copy requested rectangles of matter_in and background to staging buffers, one region per
+    // row
+    fn record_grid_readbacks(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let device = self.compute_queue.device().clone();
+        let canvas_width = self.canvas_size.x as u64;
+        for pending in self
+            .pending_readbacks
+            .iter_mut()
+            .filter(|pending| pending.recorded.is_none())
+        {
+            let size = (pending.max - pending.min + IVec2::ONE).as_uvec2();
+            let staging = || {
+                CpuAccessibleBuffer::from_iter(
+                    device.clone(),
+                    BufferUsage::transfer_dst(),
+                    true,
+                    vec![0u32; (size.x * size.y) as usize],
+                )
+                .unwrap()
+            };
+            let (matter, background) = (staging(), staging());
+            let regions = (0..size.y as u64)
+                .map(|row| BufferCopy {
+                    src_offset: (pending.min.y as u64 + row) * canvas_width + pending.min.x as u64,
+                    dst_offset: row * size.x as u64,
+                    size: size.x as u64,
+                    ..BufferCopy::default()
+                })
+                .collect::<Vec<_>>();
+            for (source, destination) in [
+                (self.matter_in.clone(), matter.clone()),
+                (self.background.clone(), background.clone()),
+            ] {
+                let mut copy_info = CopyBufferInfoTyped::buffers(source, destination);
+                copy_info.regions = regions.iter().cloned().collect();
+                builder.copy_buffer(copy_info).unwrap();
+            }
+            pending.recorded = Some((self.sim_step, matter, background));
+        }
+    }
+
+    // Count the matter inside each sensor into this step's sensor buffer for reading back
+    fn record_sensor_counts(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let buffer_index = self.readback_index();
+        // Counts not read back by now are overwritten
+        self.pending_sensor_counts
+            .retain(|pending| pending.buffer_index != buffer_index);
+        if self.sensors.is_empty() {
+            return;
+        }
+        self.sensors_buffer = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::all(),
+            false,
+            self.sensors.iter().map(|&(_, rect)| rect),
+        )
+        .unwrap();
+        if self.sensor_count_buffers[buffer_index].len()
+            < (MATTER_ID_COUNT * self.sensors.len()) as u64
+        {
+            self.sensor_count_buffers[buffer_index] =
+                empty_counts(&self.compute_queue, self.sensors.len());
+        }
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(
+                self.sensor_count_buffers[buffer_index].clone(),
+            ))
+            .unwrap();
+        self.dispatch(
+            builder,
+            self.sensor_pipeline.clone(),
+            false,
+            "sensor_pipeline",
+        );
+        self.pending_sensor_counts.push_back(PendingSensorCounts {
+            step: self.sim_step,
+            buffer_index,
+            sensors: self.sensors.clone(),
+        });
+    }
+
+    // Copy the requested cell of matter_in to this step's cell buffer
+    fn record_cell_readback(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let pos = match self.requested_cell.take() {
+            Some(pos) => pos,
+            None => return,
+        };
+        let buffer_index = self.readback_index();
+        self.pending_cells
+            .retain(|&(_, pending_index)| pending_index != buffer_index);
+        let mut copy_info = CopyBufferInfoTyped::buffers(
+            self.matter_in.clone(),
+            self.cell_buffers[buffer_index].clone(),
+        );
+        copy_info.regions[0].src_offset = self.index(pos) as u64;
+        builder.copy_buffer(copy_info).unwrap();
+        self.pending_cells.push_back((pos, buffer_index));
+    }
+
+    // Append a counting pass of matter_in into given count slot
+    fn dispatch_count(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        count_slot: u32,
+    ) {
+        self.count_slot = count_slot;
+        self.dispatch(
+            builder,
+            self.count_pipeline.clone(),
+            false,
+            "count_pipeline",
+        );
+    }
+
+    // Append a pipeline dispatch over the canvas to our command buffer
     fn dispatch(
         &mut self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         pipeline: Arc<ComputePipeline>,
         swap: bool,
+        label: &'static str,
+    ) {
+        let groups = work_groups(self.canvas_size);
+        self.dispatch_groups(builder, pipeline, swap, label, groups);
+    }
+
+    // Append a pipeline dispatch over given work groups to our command buffer
+    fn dispatch_groups(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: Arc<ComputePipeline>,
+        swap: bool,
+        label: &'static str,
+        groups: [u32; 3],
     ) {
         let pipeline_layout = pipeline.layout();
         let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
@@ -255,25 +1647,87 @@ impl CASimulator {
                 WriteDescriptorSet::buffer(0, self.matter_in.clone()),
                 WriteDescriptorSet::buffer(1, self.matter_out.clone()),
                 WriteDescriptorSet::image_view(2, self.image.clone()),
+                WriteDescriptorSet::buffer(3, self.count_buffers[self.readback_index()].clone()),
+                WriteDescriptorSet::buffer(4, self.matter_step_start.clone()),
+                WriteDescriptorSet::buffer(5, self.matter_properties.clone()),
+                WriteDescriptorSet::image_view(6, self.emission_image.clone()),
+                WriteDescriptorSet::buffer(7, self.light_in.clone()),
+                WriteDescriptorSet::buffer(8, self.light_out.clone()),
+                WriteDescriptorSet::buffer(9, self.world_objects_buffer.clone()),
+                WriteDescriptorSet::buffer(10, self.velocity_in.clone()),
+                WriteDescriptorSet::buffer(11, self.velocity_out.clone()),
+                WriteDescriptorSet::buffer(12, self.particles_in.clone()),
+                WriteDescriptorSet::buffer(13, self.particles_out.clone()),
+                WriteDescriptorSet::buffer(14, self.particle_count_in.clone()),
+                WriteDescriptorSet::buffer(15, self.particle_count_out.clone()),
+                WriteDescriptorSet::buffer(16, self.reactions_buffer.clone()),
+                WriteDescriptorSet::buffer(17, self.background.clone()),
+                WriteDescriptorSet::buffer(18, self.sensors_buffer.clone()),
+                WriteDescriptorSet::buffer(
+                    19,
+                    self.sensor_count_buffers[self.readback_index()].clone(),
+                ),
+                WriteDescriptorSet::buffer(20, self.paints_buffer.clone()),
             ],
         )
         .unwrap();
 
+        // Without lighting every cell is fully lit
+        let ambient_light = if self.light_settings.enabled {
+            self.light_settings.ambient_light
+        } else {
+            1.0
+        };
+        // Empty rectangle when there's nothing to outline
+        let (selection_min, selection_max) = self
+            .selection_outline
+            .unwrap_or((IVec2::ZERO, IVec2::splat(-1)));
         //push constants overwriting
         let push_constants = fall_empty_cs::ty::PushConstants {
+            seed: self.seed,
             sim_step: self.sim_step as u32,
             move_step: self.move_step as u32,
+            count_slot: self.count_slot,
+            view_mode: self.view_mode as u32,
+            sky_light: self.light_settings.sky_light,
+            ambient_light,
+            boundary_top: self.boundaries.get(Edge::Top) as u32,
+            boundary_bottom: self.boundaries.get(Edge::Bottom) as u32,
+            boundary_left: self.boundaries.get(Edge::Left) as u32,
+            boundary_right: self.boundaries.get(Edge::Right) as u32,
+            world_object_count: self.world_objects.len() as u32,
+            eject_x: self.ejection.center.x,
+            eject_y: self.ejection.center.y,
+            eject_radius: self.ejection.radius,
+            eject_burst: self.ejection.burst,
+            eject_velocity_x: self.ejection.velocity.x,
+            eject_velocity_y: self.ejection.velocity.y,
+            reaction_count: self.reaction_count,
+            background_opacity: self.light_settings.background_opacity,
+            selection_min_x: selection_min.x,
+            selection_min_y: selection_min.y,
+            selection_max_x: selection_max.x,
+            selection_max_y: selection_max.y,
+            sensor_count: self.sensors.len() as u32,
+            paint_count: self.paint_count,
         };
 
-        builder
-            .bind_pipeline_compute(pipeline.clone())
-            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
-            .push_constants(pipeline_layout.clone(), 0, push_constants)
-            .dispatch([NUM_WORK_GROUPS_X, NUM_WORK_GROUPS_Y, 1])
-            .unwrap();
+        let record = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>| {
+            builder
+                .bind_pipeline_compute(pipeline.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
+                .push_constants(pipeline_layout.clone(), 0, push_constants)
+                .dispatch(groups)
+                .unwrap();
+        };
+        match &mut self.profiler {
+            Some(profiler) => profiler.scope(builder, label, record),
+            None => record(builder),
+        }
 
         if swap {
             std::mem::swap(&mut self.matter_in, &mut self.matter_out);
+            std::mem::swap(&mut self.velocity_in, &mut self.velocity_out);
         }
     }
 
@@ -283,8 +1737,86 @@ impl CASimulator {
         &mut self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         pipeline: Arc<ComputePipeline>,
+        pass_name: &'static str,
     ) {
-        self.dispatch(builder, pipeline.clone(), true);
+        self.dispatch(builder, pipeline.clone(), true, pass_name);
         self.move_step += 1;
+        self.check_pass(builder, pass_name);
+    }
+
+    // Count the population after a pass when conservation checks are on
+    fn check_pass(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pass_name: &'static str,
+    ) {
+        if self.check_conservation {
+            // Buffers were swapped, so matter_in is now the output of the pass
+            self.checked_passes.push(pass_name);
+            self.dispatch_count(builder, self.checked_passes.len() as u32);
+        }
+    }
+
+    // Eject pending matter into particles, then move particles and land those that hit
+    // something
+    fn step_particles(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        for ejection in std::mem::take(&mut self.pending_ejections) {
+            self.ejection = ejection;
+            self.dispatch(
+                builder,
+                self.eject_pipeline.clone(),
+                false,
+                "eject_pipeline",
+            );
+            self.check_pass(builder, "eject_pipeline");
+        }
+
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(self.particle_count_out.clone()))
+            .unwrap();
+        self.dispatch_groups(
+            builder,
+            self.particle_pipeline.clone(),
+            false,
+            "particle_pipeline",
+            particle_work_groups(),
+        );
+        std::mem::swap(&mut self.particles_in, &mut self.particles_out);
+        std::mem::swap(&mut self.particle_count_in, &mut self.particle_count_out);
+        self.check_pass(builder, "particle_pipeline");
+    }
+
+    // Upload world objects and append a pass applying them to the grid
+    fn apply_world_objects(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        if self.world_objects.is_empty() {
+            return;
+        }
+        self.world_objects_buffer = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::all(),
+            false,
+            self.world_objects.clone(),
+        )
+        .unwrap();
+        self.dispatch(
+            builder,
+            self.world_objects_pipeline.clone(),
+            true,
+            "world_objects_pipeline",
+        );
+    }
+
+    // Append a light propagation pass from light_in to light_out and swap them
+    fn step_light(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        self.dispatch(
+            builder,
+            self.light_pipeline.clone(),
+            false,
+            "light_pipeline",
+        );
+        std::mem::swap(&mut self.light_in, &mut self.light_out);
     }
 }
@@ -0,0 +1,40 @@
+use bevy::math::{IVec2, Vec2};
+use bytemuck::{Pod, Zeroable};
+
+// Capacity of the particle buffers. Must match MAX_PARTICLES in includes.glsl and be a multiple
+// of the work group size, see particle_work_groups
+pub const MAX_PARTICLES: usize = 65536;
+
+// Matter flying freely outside the grid. Must match Particle in includes.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+pub struct Particle {
+    pub pos: [f32; 2],
+    pub velocity: [f32; 2],
+    pub matter: u32,
+    padding: u32,
+}
+
+// Turns matter with gravity inside a circle into particles, e.g. for explosions or
+// throwing matter
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Ejection {
+    pub center: IVec2,
+    pub radius: i32,
+    // Velocity of every particle in cells per step
+    pub velocity: Vec2,
+    // Additional speed away from the center
+    pub burst: f32,
+}
+
+impl Ejection {
+    // Ejection flinging matter away from the center and upwards
+    pub fn explosion(center: IVec2, radius: i32, strength: f32) -> Ejection {
+        Ejection {
+            center,
+            radius,
+            velocity: Vec2::new(0.0, strength / 2.0),
+            burst: strength,
+        }
+    }
+}
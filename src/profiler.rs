@@ -0,0 +1,199 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use bevy::prelude::*;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    device::Queue,
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+use crate::{particle_simulator::CASimulator, render::FillScreenRenderPass};
+
+// Query pools in flight. Results are read when a pool is about to be reused, so reading
+// them never waits for the gpu
+const PROFILER_FRAMES: usize = 3;
+// Maximum number of timed scopes per frame
+const MAX_SCOPES: u32 = 32;
+// How many frames are averaged for display
+pub const GPU_TIMING_AVERAGE_FRAMES: usize = 60;
+// How many samples are kept for csv export
+const MAX_RECORDED_SAMPLES: usize = 100_000;
+
+// Measures gpu time of scopes in a command buffer with timestamp queries
+pub struct GpuProfiler {
+    query_pools: Vec<Arc<QueryPool>>,
+    scopes: Vec<Vec<&'static str>>,
+    frame: usize,
+    // Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    finished: Vec<(&'static str, f32)>,
+}
+
+impl GpuProfiler {
+    // Returns None if the queue does not support timestamps
+    pub fn new(queue: &Arc<Queue>) -> Option<GpuProfiler> {
+        queue.family().timestamp_valid_bits()?;
+        let query_pools = (0..PROFILER_FRAMES)
+            .map(|_| {
+                QueryPool::new(
+                    queue.device().clone(),
+                    QueryPoolCreateInfo {
+                        query_count: MAX_SCOPES * 2,
+                        ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                    },
+                )
+                .unwrap()
+            })
+            .collect();
+        Some(GpuProfiler {
+            query_pools,
+            scopes: vec![vec![]; PROFILER_FRAMES],
+            frame: 0,
+            timestamp_period: queue
+                .device()
+                .physical_device()
+                .properties()
+                .timestamp_period,
+            finished: vec![],
+        })
+    }
+
+    // Start a new frame of scopes. Must be recorded outside of render passes.
+    pub fn begin_frame(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        self.frame = (self.frame + 1) % PROFILER_FRAMES;
+        self.read_results(self.frame);
+        let pool = self.query_pools[self.frame].clone();
+        unsafe {
+            builder.reset_query_pool(pool, 0..MAX_SCOPES * 2).unwrap();
+        }
+    }
+
+    // Time commands recorded by `record` under label. Scopes past the limit are not timed.
+    pub fn scope<R>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        label: &'static str,
+        record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> R,
+    ) -> R {
+        let scope = self.scopes[self.frame].len() as u32;
+        if scope >= MAX_SCOPES {
+            return record(builder);
+        }
+        let pool = self.query_pools[self.frame].clone();
+        // Queries are reset in begin_frame
+        unsafe {
+            builder
+                .write_timestamp(pool.clone(), scope * 2, PipelineStage::TopOfPipe)
+                .unwrap();
+        }
+        let result = record(builder);
+        unsafe {
+            builder
+                .write_timestamp(pool, scope * 2 + 1, PipelineStage::BottomOfPipe)
+                .unwrap();
+        }
+        self.scopes[self.frame].push(label);
+        result
+    }
+
+    // Timings (label, milliseconds) of finished frames since the last call. Scopes with the
+    // same label within a frame are summed.
+    pub fn take_timings(&mut self) -> Vec<(&'static str, f32)> {
+        std::mem::take(&mut self.finished)
+    }
+
+    fn read_results(&mut self, frame: usize) {
+        let scopes = std::mem::take(&mut self.scopes[frame]);
+        if scopes.is_empty() {
+            return;
+        }
+        let mut timestamps = vec![0u64; scopes.len() * 2];
+        let available = self.query_pools[frame]
+            .queries_range(0..scopes.len() as u32 * 2)
+            .unwrap()
+            .get_results(&mut timestamps, QueryResultFlags::default())
+            .unwrap_or(false);
+        // Frames that have not finished by now are skipped
+        if !available {
+            return;
+        }
+        let mut frame_timings: Vec<(&'static str, f32)> = vec![];
+        for (label, pair) in scopes.iter().zip(timestamps.chunks(2)) {
+            let ms = pair[1].saturating_sub(pair[0]) as f32 * self.timestamp_period / 1_000_000.0;
+            match frame_timings.iter_mut().find(|(l, _)| l == label) {
+                Some((_, total)) => *total += ms,
+                None => frame_timings.push((*label, ms)),
+            }
+        }
+        self.finished.extend(frame_timings);
+    }
+}
+
+// Gpu milliseconds spent in each pass
+#[derive(Default)]
+pub struct GpuTimings {
+    pub passes: BTreeMap<&'static str, VecDeque<f32>>,
+    // (seconds since startup, pass, milliseconds) for csv export
+    samples: VecDeque<(f64, &'static str, f32)>,
+}
+
+impl GpuTimings {
+    // Rolling average of the latest frames
+    pub fn average(&self, pass: &str) -> Option<f32> {
+        let timings = self.passes.get(pass)?;
+        if timings.is_empty() {
+            return None;
+        }
+        Some(timings.iter().sum::<f32>() / timings.len() as f32)
+    }
+
+    fn push(&mut self, seconds: f64, pass: &'static str, ms: f32) {
+        let timings = self.passes.entry(pass).or_default();
+        if timings.len() == GPU_TIMING_AVERAGE_FRAMES {
+            timings.pop_front();
+        }
+        timings.push_back(ms);
+        if self.samples.len() == MAX_RECORDED_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((seconds, pass, ms));
+    }
+
+    // Write recorded samples as csv with header time_s,pass,gpu_ms
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        writeln!(file, "time_s,pass,gpu_ms")?;
+        for (seconds, pass, ms) in self.samples.iter() {
+            writeln!(file, "{:.6},{},{:.6}", seconds, pass, ms)?;
+        }
+        file.flush()
+    }
+}
+
+// Collect finished gpu timings of simulation and rendering
+pub fn record_gpu_timings(
+    time: Res<Time>,
+    mut simulator: ResMut<CASimulator>,
+    // Only there with SandSimRenderPlugin
+    fill_screen: Option<ResMut<FillScreenRenderPass>>,
+    mut timings: ResMut<GpuTimings>,
+) {
+    let seconds = time.seconds_since_startup();
+    let finished = simulator
+        .take_gpu_timings()
+        .into_iter()
+        .chain(fill_screen.map_or(vec![], |mut fill_screen| fill_screen.take_gpu_timings()));
+    for (pass, ms) in finished {
+        timings.push(seconds, pass, ms);
+    }
+}
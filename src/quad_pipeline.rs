@@ -1,5 +1,7 @@
 use std::sync::Arc;
 
+use bevy::math::{Mat4, Vec2};
+
 use vulkano::{
     buffer::TypedBufferAccess,
     command_buffer::{
@@ -10,6 +12,7 @@ use vulkano::{
     image::ImageViewAbstract,
     pipeline::{
         graphics::{
+            color_blend::ColorBlendState,
             input_assembly::InputAssemblyState,
             vertex_input::BuffersDefinition,
             viewport::{Viewport, ViewportState},
@@ -27,6 +30,13 @@ use crate::{
 
 use vulkano::image::ImageAccess;
 
+// Placement of a 1.0 sized quad: world_to_screen positions it and scale sizes it in world units
+#[derive(Debug, Copy, Clone)]
+pub struct QuadTransform {
+    pub world_to_screen: Mat4,
+    pub scale: Vec2,
+}
+
 // Pipeline to draw pixel perfect images on quads
 pub struct DrawQuadPipeline {
     gfx_queue: Arc<Queue>,
@@ -37,6 +47,19 @@ pub struct DrawQuadPipeline {
 
 impl DrawQuadPipeline {
     pub fn new(gfx_queue: Arc<Queue>, subpass: Subpass) -> DrawQuadPipeline {
+        Self::with_color_blend(gfx_queue, subpass, ColorBlendState::new(1))
+    }
+
+    // Pipeline which adds image colors to the target instead of replacing them
+    pub fn new_additive(gfx_queue: Arc<Queue>, subpass: Subpass) -> DrawQuadPipeline {
+        Self::with_color_blend(gfx_queue, subpass, ColorBlendState::new(1).blend_additive())
+    }
+
+    fn with_color_blend(
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        color_blend_state: ColorBlendState,
+    ) -> DrawQuadPipeline {
         let quad = TexturedQuad::new(1.0, 1.0, [1.0; 4]).to_mesh(gfx_queue.device().clone());
         let pipeline = {
             let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
@@ -47,6 +70,7 @@ impl DrawQuadPipeline {
                 .input_assembly_state(InputAssemblyState::new())
                 .fragment_shader(fs.entry_point("main").unwrap(), ())
                 .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .color_blend_state(color_blend_state)
                 .render_pass(subpass.clone())
                 .build(gfx_queue.device().clone())
                 .unwrap()
@@ -67,6 +91,33 @@ impl DrawQuadPipeline {
         image: Arc<dyn ImageViewAbstract>,
         flip_x: bool,
         flip_y: bool,
+    ) -> SecondaryAutoCommandBuffer {
+        let dims = image.image().dimensions();
+        // Scale transforms our 1.0 sized quad to actual pixel size in screen space
+        let scale = Vec2::new(
+            dims.width() as f32 * if flip_x { -1.0 } else { 1.0 },
+            dims.height() as f32 * if flip_y { -1.0 } else { 1.0 },
+        );
+        self.draw_quads(
+            Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [viewport_dimensions[0] as f32, viewport_dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            },
+            image,
+            &[QuadTransform {
+                world_to_screen: camera.world_to_screen(),
+                scale,
+            }],
+        )
+    }
+
+    // Draw input `image` on each of the quads inside given viewport
+    pub fn draw_quads(
+        &mut self,
+        viewport: Viewport,
+        image: Arc<dyn ImageViewAbstract>,
+        quads: &[QuadTransform],
     ) -> SecondaryAutoCommandBuffer {
         let mut builder = AutoCommandBufferBuilder::secondary(
             self.gfx_queue.device().clone(),
@@ -78,30 +129,13 @@ impl DrawQuadPipeline {
             },
         )
         .unwrap();
-        let dims = image.image().dimensions();
-        let push_constants = vs::ty::PushConstants {
-            world_to_screen: camera.world_to_screen().to_cols_array_2d(),
-            // Scale transforms our 1.0 sized quad to actual pixel size in screen space
-            scale: [
-                dims.width() as f32 * if flip_x { -1.0 } else { 1.0 },
-                dims.height() as f32 * if flip_y { -1.0 } else { 1.0 },
-            ],
-        };
-
         let image_sampler_descriptor_set = create_image_sampler_nearest_descriptor_set(
             self.gfx_queue.device().clone(),
             self.pipeline.clone(),
             image,
         );
         builder
-            .set_viewport(
-                0,
-                [Viewport {
-                    origin: [0.0, 0.0],
-                    dimensions: [viewport_dimensions[0] as f32, viewport_dimensions[1] as f32],
-                    depth_range: 0.0..1.0,
-                }],
-            )
+            .set_viewport(0, [viewport])
             .bind_pipeline_graphics(self.pipeline.clone())
             .bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
@@ -109,11 +143,18 @@ impl DrawQuadPipeline {
                 0,
                 image_sampler_descriptor_set,
             )
-            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
             .bind_vertex_buffers(0, self.quad.vertices.clone())
-            .bind_index_buffer(self.quad.indices.clone())
-            .draw_indexed(self.quad.indices.len() as u32, 1, 0, 0, 0)
-            .unwrap();
+            .bind_index_buffer(self.quad.indices.clone());
+        for quad in quads {
+            let push_constants = vs::ty::PushConstants {
+                world_to_screen: quad.world_to_screen.to_cols_array_2d(),
+                scale: quad.scale.to_array(),
+            };
+            builder
+                .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+                .draw_indexed(self.quad.indices.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
         builder.build().unwrap()
     }
 }
@@ -0,0 +1,94 @@
+use std::{fs, path::Path};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    matter::{MatterId, MatterWithColor},
+    particle_simulator::CASimulator,
+};
+
+// Where reactions are loaded from at startup
+pub const REACTIONS_PATH: &str = "data/reactions.txt";
+
+// Reaction of two neighboring cells uploaded to the gpu. Must match Reaction in includes.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct Reaction {
+    // Matter ids of the reacting cells
+    pub a: u32,
+    pub b: u32,
+    // Matter values (with color) replacing a and b
+    pub a_out: u32,
+    pub b_out: u32,
+    // Chance of reacting per step
+    pub probability: f32,
+}
+
+fn parse_matter(name: &str, line_number: usize) -> Result<MatterId, String> {
+    MatterId::from_name(name)
+        .ok_or_else(|| format!("line {}: unknown matter '{}'", line_number, name))
+}
+
+// Parse reactions from lines of `A + B -> A' + B' probability`. Empty lines and lines
+// starting with # are skipped.
+pub fn parse_reactions(text: &str) -> Result<Vec<Reaction>, String> {
+    let mut reactions = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        let (a, b, a_out, b_out, probability) = match tokens[..] {
+            [a, "+", b, "->", a_out, "+", b_out, probability] => (a, b, a_out, b_out, probability),
+            _ => {
+                return Err(format!(
+                    "line {}: expected `A + B -> A' + B' probability`, got `{}`",
+                    line_number, line
+                ))
+            }
+        };
+        let probability = probability
+            .parse::<f32>()
+            .ok()
+            .filter(|p| (0.0..=1.0).contains(p))
+            .ok_or_else(|| {
+                format!(
+                    "line {}: probability must be a number from 0 to 1, got '{}'",
+                    line_number, probability
+                )
+            })?;
+        reactions.push(Reaction {
+            a: parse_matter(a, line_number)? as u32,
+            b: parse_matter(b, line_number)? as u32,
+            a_out: MatterWithColor::new(parse_matter(a_out, line_number)?).value,
+            b_out: MatterWithColor::new(parse_matter(b_out, line_number)?).value,
+            probability,
+        });
+    }
+    Ok(reactions)
+}
+
+// Load reactions from a file, see `parse_reactions`
+pub fn load_reactions(path: impl AsRef<Path>) -> Result<Vec<Reaction>, String> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_reactions(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Load the reaction table into the simulator, keeping the previous table on errors
+pub fn reload_reactions(simulator: &mut CASimulator) {
+    match load_reactions(REACTIONS_PATH) {
+        Ok(reactions) => {
+            bevy::log::info!(
+                "Loaded {} reactions from {}",
+                reactions.len(),
+                REACTIONS_PATH
+            );
+            simulator.set_reactions(&reactions);
+        }
+        Err(e) => bevy::log::error!("{}", e),
+    }
+}
@@ -2,16 +2,27 @@ use std::sync::Arc;
 
 use vulkano::{
     command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassContents,
+        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
+        RenderPassBeginInfo, SecondaryAutoCommandBuffer, SubpassContents,
     },
     device::Queue,
     format::Format,
-    image::{ImageAccess, ImageViewAbstract},
+    image::{
+        view::ImageView, ImageAccess, ImageDimensions, ImageViewAbstract, ImmutableImage,
+        MipmapsCount,
+    },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sync::GpuFuture,
 };
 
-use crate::{camera::OrthographicCamera, quad_pipeline::DrawQuadPipeline};
+use bevy::math::{Mat4, Vec2};
+
+use crate::{
+    camera::OrthographicCamera,
+    minimap::Minimap,
+    profiler::GpuProfiler,
+    quad_pipeline::{DrawQuadPipeline, QuadTransform},
+};
 use vulkano_util::renderer::SwapchainImageView;
 
 // A render pass which places an image over screen frame
@@ -19,8 +30,16 @@ pub struct FillScreenRenderPass {
     gfx_queue: Arc<Queue>,
     render_pass: Arc<RenderPass>,
     quad_pipeline: DrawQuadPipeline,
+    // Adds the glow of emissive matter over the canvas
+    glow_pipeline: DrawQuadPipeline,
+    profiler: Option<GpuProfiler>,
+    // Single white pixel for drawing plain rectangles
+    marker_image: Arc<dyn ImageViewAbstract>,
 }
 
+// Width of the minimap's view rectangle edges in pixels
+const MINIMAP_VIEW_EDGE_WIDTH: f32 = 2.0;
+
 impl FillScreenRenderPass {
     pub fn new(gfx_queue: Arc<Queue>, output_format: Format) -> FillScreenRenderPass {
         let render_pass = vulkano::single_pass_renderpass!(gfx_queue.device().clone(),
@@ -40,16 +59,46 @@ impl FillScreenRenderPass {
         )
         .unwrap();
         let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
-        let quad_pipeline = DrawQuadPipeline::new(gfx_queue.clone(), subpass);
+        let quad_pipeline = DrawQuadPipeline::new(gfx_queue.clone(), subpass.clone());
+        let glow_pipeline = DrawQuadPipeline::new_additive(gfx_queue.clone(), subpass);
+        let profiler = GpuProfiler::new(&gfx_queue);
+        let (marker_image, marker_future) = ImmutableImage::from_iter(
+            [255u8; 4],
+            ImageDimensions::Dim2d {
+                width: 1,
+                height: 1,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+            gfx_queue.clone(),
+        )
+        .unwrap();
+        marker_future
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
         FillScreenRenderPass {
             gfx_queue,
             render_pass,
             quad_pipeline,
+            glow_pipeline,
+            profiler,
+            marker_image: ImageView::new_default(marker_image).unwrap(),
         }
     }
 
+    // Gpu milliseconds of draws finished since the last call
+    pub fn take_gpu_timings(&mut self) -> Vec<(&'static str, f32)> {
+        self.profiler
+            .as_mut()
+            .map_or_else(Vec::new, |profiler| profiler.take_timings())
+    }
+
     // Place view exactly over swapchain image target.
     // Texture draw pipeline uses a quad onto which it places the view.
+    // Glow is added over the view with the same transform.
     pub fn draw<F>(
         &mut self,
         before_future: F,
@@ -59,6 +108,8 @@ impl FillScreenRenderPass {
         clear_color: [f32; 4],
         flip_x: bool,
         flip_y: bool,
+        minimap: Option<Minimap>,
+        glow: Option<Arc<dyn ImageViewAbstract>>,
     ) -> Box<dyn GpuFuture>
     where
         F: GpuFuture + 'static,
@@ -81,26 +132,125 @@ impl FillScreenRenderPass {
             CommandBufferUsage::OneTimeSubmit,
         )
         .unwrap();
-        command_buffer_builder
-            .begin_render_pass(
-                RenderPassBeginInfo {
-                    clear_values: vec![Some(clear_color.into())],
-                    ..RenderPassBeginInfo::framebuffer(framebuffer)
-                },
-                SubpassContents::SecondaryCommandBuffers,
-            )
-            .unwrap();
-        // Create secondary command buffer from quad pipeline (subpass) and execute it inside render pass.
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame(&mut command_buffer_builder);
+        }
+        // Create secondary command buffers from quad pipeline (subpass) and execute them inside render pass.
         // Then build the primary command buffer and execute it.
-        let cb =
-            self.quad_pipeline
-                .draw(target_image.width_height(), camera, image, flip_x, flip_y);
-        command_buffer_builder.execute_commands(cb).unwrap();
-        command_buffer_builder.end_render_pass().unwrap();
+        let mut cbs = vec![self.quad_pipeline.draw(
+            target_image.width_height(),
+            camera,
+            image.clone(),
+            flip_x,
+            flip_y,
+        )];
+        if let Some(glow) = glow {
+            cbs.push(self.glow_pipeline.draw(
+                target_image.width_height(),
+                camera,
+                glow,
+                flip_x,
+                flip_y,
+            ));
+        }
+        if let Some(minimap) = minimap.filter(|minimap| minimap.visible) {
+            cbs.extend(self.draw_minimap(
+                minimap,
+                camera,
+                image,
+                target_image.width_height(),
+                flip_x,
+                flip_y,
+            ));
+        }
+        let record =
+            |command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>| {
+                command_buffer_builder
+                    .begin_render_pass(
+                        RenderPassBeginInfo {
+                            clear_values: vec![Some(clear_color.into())],
+                            ..RenderPassBeginInfo::framebuffer(framebuffer)
+                        },
+                        SubpassContents::SecondaryCommandBuffers,
+                    )
+                    .unwrap();
+                for cb in cbs {
+                    command_buffer_builder.execute_commands(cb).unwrap();
+                }
+                command_buffer_builder.end_render_pass().unwrap();
+            };
+        match &mut self.profiler {
+            Some(profiler) => profiler.scope(&mut command_buffer_builder, "quad_draw", record),
+            None => record(&mut command_buffer_builder),
+        }
         let command_buffer = command_buffer_builder.build().unwrap();
         before_future
             .then_execute(self.gfx_queue.clone(), command_buffer)
             .unwrap()
             .boxed()
     }
+
+    // Draw the whole canvas into the minimap viewport with a rectangle around the area
+    // visible through `camera`
+    fn draw_minimap(
+        &mut self,
+        minimap: Minimap,
+        camera: OrthographicCamera,
+        image: Arc<dyn ImageViewAbstract>,
+        target_dimensions: [u32; 2],
+        flip_x: bool,
+        flip_y: bool,
+    ) -> [SecondaryAutoCommandBuffer; 2] {
+        let viewport = minimap.viewport(Vec2::new(
+            target_dimensions[0] as f32,
+            target_dimensions[1] as f32,
+        ));
+        let minimap_camera = minimap.camera();
+        let minimap_world_to_screen = minimap_camera.world_to_screen();
+        let dims = image.image().dimensions();
+        let canvas_quad = QuadTransform {
+            world_to_screen: minimap_world_to_screen,
+            scale: Vec2::new(
+                dims.width() as f32 * if flip_x { -1.0 } else { 1.0 },
+                dims.height() as f32 * if flip_y { -1.0 } else { 1.0 },
+            ),
+        };
+        let canvas_cb = self
+            .quad_pipeline
+            .draw_quads(viewport.clone(), image, &[canvas_quad]);
+
+        // View rectangle edges in world coordinates
+        let view_center = -camera.pos;
+        let view_size =
+            Vec2::new(camera.right - camera.left, camera.top - camera.bottom) * camera.scale;
+        let edge_width = MINIMAP_VIEW_EDGE_WIDTH * minimap_camera.scale;
+        let half_size = view_size / 2.0;
+        let edges = [
+            (
+                Vec2::new(0.0, half_size.y),
+                Vec2::new(view_size.x, edge_width),
+            ),
+            (
+                Vec2::new(0.0, -half_size.y),
+                Vec2::new(view_size.x, edge_width),
+            ),
+            (
+                Vec2::new(-half_size.x, 0.0),
+                Vec2::new(edge_width, view_size.y),
+            ),
+            (
+                Vec2::new(half_size.x, 0.0),
+                Vec2::new(edge_width, view_size.y),
+            ),
+        ]
+        .map(|(offset, scale)| QuadTransform {
+            world_to_screen: minimap_world_to_screen
+                * Mat4::from_translation((view_center + offset).extend(0.0)),
+            scale,
+        });
+        let view_cb = self
+            .quad_pipeline
+            .draw_quads(viewport, self.marker_image.clone(), &edges);
+        [canvas_cb, view_cb]
+    }
 }
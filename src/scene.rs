@@ -0,0 +1,443 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    background::BackgroundId,
+    camera::OrthographicCamera,
+    matter::{matter_name, MatterId, MatterWithColor},
+    particle_simulator::{CASimulator, ReadbackHandle},
+    world_objects::{Drain, Emitter, Shape},
+    LOCAL_SIZE_X, LOCAL_SIZE_Y,
+};
+
+// Where the scene browser looks for scenes
+pub const SCENES_DIR: &str = "scenes";
+
+// Largest canvas side of a scene, far beyond what devices simulate, so that corrupt sizes fail
+// before any grid is decoded
+const MAX_CANVAS_SIDE: u32 = 16384;
+
+// Shape of an emitter or drain as stored in scene files
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+enum SceneShape {
+    Circle { center: [i32; 2], radius: i32 },
+    Rect { min: [i32; 2], max: [i32; 2] },
+}
+
+impl From<Shape> for SceneShape {
+    fn from(shape: Shape) -> Self {
+        match shape {
+            Shape::Circle { center, radius } => SceneShape::Circle {
+                center: center.to_array(),
+                radius,
+            },
+            Shape::Rect { min, max } => SceneShape::Rect {
+                min: min.to_array(),
+                max: max.to_array(),
+            },
+        }
+    }
+}
+
+impl From<SceneShape> for Shape {
+    fn from(shape: SceneShape) -> Self {
+        match shape {
+            SceneShape::Circle { center, radius } => Shape::Circle {
+                center: IVec2::from(center),
+                radius,
+            },
+            SceneShape::Rect { min, max } => Shape::Rect {
+                min: IVec2::from(min),
+                max: IVec2::from(max),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SceneEmitter {
+    matter: String,
+    shape: SceneShape,
+    rate: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SceneCamera {
+    pos: [f32; 2],
+    scale: f32,
+}
+
+// Scene file contents. Grids are stored as runs of (palette index, length), rows bottom to top,
+// and materials by name so that files survive changes to matter ids.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SceneFile {
+    name: String,
+    description: String,
+    canvas_size: [u32; 2],
+    camera: SceneCamera,
+    palette: Vec<String>,
+    grid: Vec<[u32; 2]>,
+    background_palette: Vec<String>,
+    background: Vec<[u32; 2]>,
+    emitters: Vec<SceneEmitter>,
+    drains: Vec<SceneShape>,
+}
+
+// Everything needed to restore a simulation, see `save_scene` and `load_scene`
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub name: String,
+    pub description: String,
+    pub canvas_size: UVec2,
+    pub camera_pos: Vec2,
+    pub camera_scale: f32,
+    // Matter and background values of each cell
+    pub matter: Vec<u32>,
+    pub background: Vec<u32>,
+    pub emitters: Vec<Emitter>,
+    pub drains: Vec<Drain>,
+}
+
+// Run length encode cell ids into palette indices. Palette entries are named by `name`.
+fn encode_runs(
+    ids: impl Iterator<Item = u8>,
+    name: impl Fn(u8) -> String,
+) -> (Vec<String>, Vec<[u32; 2]>) {
+    let mut palette_ids: Vec<u8> = vec![];
+    let mut runs: Vec<[u32; 2]> = vec![];
+    for id in ids {
+        let index = match palette_ids.iter().position(|&palette_id| palette_id == id) {
+            Some(index) => index,
+            None => {
+                palette_ids.push(id);
+                palette_ids.len() - 1
+            }
+        } as u32;
+        match runs.last_mut() {
+            Some(run) if run[0] == index => run[1] += 1,
+            _ => runs.push([index, 1]),
+        }
+    }
+    (palette_ids.into_iter().map(name).collect(), runs)
+}
+
+// Expand runs into cell values, resolving palette names with `value`
+fn decode_runs(
+    palette: &[String],
+    runs: &[[u32; 2]],
+    cell_count: usize,
+    value: impl Fn(&str) -> Option<u32>,
+    kind: &str,
+) -> Result<Vec<u32>, String> {
+    let values = palette
+        .iter()
+        .map(|name| {
+            value(name).ok_or_else(|| format!("{} '{}' is not known to this build", kind, name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut cells = Vec::with_capacity(cell_count);
+    for &[index, length] in runs {
+        let cell = values
+            .get(index as usize)
+            .ok_or_else(|| format!("{} palette has no entry {}", kind, index))?;
+        if length as usize > cell_count - cells.len() {
+            return Err(format!(
+                "{} grid has more than the expected {} cells",
+                kind, cell_count
+            ));
+        }
+        cells.extend(std::iter::repeat(*cell).take(length as usize));
+    }
+    if cells.len() != cell_count {
+        return Err(format!(
+            "{} grid has {} cells, expected {}",
+            kind,
+            cells.len(),
+            cell_count
+        ));
+    }
+    Ok(cells)
+}
+
+impl Scene {
+    fn to_file(&self) -> SceneFile {
+        let (palette, grid) = encode_runs(
+            self.matter
+                .iter()
+                .map(|&value| MatterWithColor::from(value).matter_id()),
+            matter_name,
+        );
+        let (background_palette, background) = encode_runs(
+            self.background.iter().map(|&value| (value & 255) as u8),
+            |id| BackgroundId::from_id(id).map_or_else(|| id.to_string(), |b| format!("{:?}", b)),
+        );
+        SceneFile {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            canvas_size: self.canvas_size.to_array(),
+            camera: SceneCamera {
+                pos: self.camera_pos.to_array(),
+                scale: self.camera_scale,
+            },
+            palette,
+            grid,
+            background_palette,
+            background,
+            emitters: self
+                .emitters
+                .iter()
+                .map(|emitter| SceneEmitter {
+                    matter: format!("{:?}", emitter.matter),
+                    shape: emitter.shape.into(),
+                    rate: emitter.rate,
+                })
+                .collect(),
+            drains: self.drains.iter().map(|drain| drain.shape.into()).collect(),
+        }
+    }
+
+    fn from_file(file: SceneFile) -> Result<Scene, String> {
+        let canvas_size = UVec2::from(file.canvas_size);
+        if canvas_size.x == 0
+            || canvas_size.y == 0
+            || canvas_size.x % LOCAL_SIZE_X != 0
+            || canvas_size.y % LOCAL_SIZE_Y != 0
+            || canvas_size.max_element() > MAX_CANVAS_SIDE
+        {
+            return Err(format!(
+                "scene canvas {}x{} must be a positive multiple of {}x{} of at most {}x{}",
+                canvas_size.x,
+                canvas_size.y,
+                LOCAL_SIZE_X,
+                LOCAL_SIZE_Y,
+                MAX_CANVAS_SIDE,
+                MAX_CANVAS_SIDE
+            ));
+        }
+        let cell_count = canvas_size.x.checked_mul(canvas_size.y).ok_or_else(|| {
+            format!(
+                "scene canvas {}x{} is too large",
+                canvas_size.x, canvas_size.y
+            )
+        })? as usize;
+        let matter = decode_runs(
+            &file.palette,
+            &file.grid,
+            cell_count,
+            |name| MatterId::from_name(name).map(|matter| MatterWithColor::new(matter).value),
+            "matter",
+        )?;
+        let background = decode_runs(
+            &file.background_palette,
+            &file.background,
+            cell_count,
+            |name| BackgroundId::from_name(name).map(|background| background.value()),
+            "background",
+        )?;
+        let emitters = file
+            .emitters
+            .iter()
+            .map(|emitter| {
+                let matter = MatterId::from_name(&emitter.matter).ok_or_else(|| {
+                    format!("matter '{}' is not known to this build", emitter.matter)
+                })?;
+                Ok(Emitter {
+                    matter,
+                    shape: emitter.shape.into(),
+                    rate: emitter.rate,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Scene {
+            name: file.name,
+            description: file.description,
+            canvas_size,
+            camera_pos: Vec2::from(file.camera.pos),
+            camera_scale: file.camera.scale,
+            matter,
+            background,
+            emitters,
+            drains: file
+                .drains
+                .into_iter()
+                .map(|shape| Drain {
+                    shape: shape.into(),
+                })
+                .collect(),
+        })
+    }
+}
+
+pub fn save_scene(scene: &Scene, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let text = serde_json::to_string(&scene.to_file()).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Load a scene, failing if it doesn't fit this build, e.g. uses unknown materials
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, String> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let file = serde_json::from_str::<SceneFile>(&text)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Scene::from_file(file).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Scene files in SCENES_DIR sorted by name
+pub fn scene_files() -> Vec<PathBuf> {
+    let mut files = fs::read_dir(SCENES_DIR).map_or_else(
+        |_| vec![],
+        |entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+                .collect()
+        },
+    );
+    files.sort();
+    files
+}
+
+// What the scene system does next
+pub enum SceneRequest {
+    Load(Scene),
+    Save(PathBuf),
+}
+
+// Metadata of the current scene and requests from the gui or the command line
+pub struct SceneLibrary {
+    pub name: String,
+    pub description: String,
+    // Path the gui loads from and saves to
+    pub path: String,
+    pub request: Option<SceneRequest>,
+    // Result of the latest load or save, shown in the gui
+    pub status: Option<Result<String, String>>,
+    // Save waiting for the gpu to copy the grids
+    saving: Option<(PathBuf, ReadbackHandle)>,
+}
+
+impl Default for SceneLibrary {
+    fn default() -> Self {
+        Self {
+            name: "Untitled".to_string(),
+            description: String::new(),
+            path: format!("{}/untitled.json", SCENES_DIR),
+            request: None,
+            status: None,
+            saving: None,
+        }
+    }
+}
+
+impl SceneLibrary {
+    // Start with a scene loaded from a path, e.g. given on the command line
+    pub fn with_scene(path: &Path, scene: Scene) -> SceneLibrary {
+        SceneLibrary {
+            path: path.display().to_string(),
+            request: Some(SceneRequest::Load(scene)),
+            ..SceneLibrary::default()
+        }
+    }
+
+    // Load a scene from `path` on the next frame
+    pub fn request_load(&mut self) {
+        match load_scene(&self.path) {
+            Ok(scene) => self.request = Some(SceneRequest::Load(scene)),
+            Err(e) => {
+                bevy::log::error!("{}", e);
+                self.status = Some(Err(e));
+            }
+        }
+    }
+}
+
+// System that replaces the simulation with a loaded scene or saves the current one. Saving
+// reads the grids back asynchronously, so the file is written a few frames after the request.
+pub fn handle_scene_requests(
+    mut library: ResMut<SceneLibrary>,
+    mut simulator: ResMut<CASimulator>,
+    mut camera: ResMut<OrthographicCamera>,
+    mut commands: Commands,
+    emitters: Query<(Entity, &Emitter)>,
+    drains: Query<(Entity, &Drain)>,
+) {
+    match library.request.take() {
+        Some(SceneRequest::Load(scene)) if scene.canvas_size != simulator.canvas_size() => {
+            let e = format!(
+                "Scene {} is {}x{} but the canvas is {}x{}, restart with it as argument instead",
+                scene.name,
+                scene.canvas_size.x,
+                scene.canvas_size.y,
+                simulator.canvas_size().x,
+                simulator.canvas_size().y
+            );
+            bevy::log::error!("{}", e);
+            library.status = Some(Err(e));
+        }
+        Some(SceneRequest::Load(scene)) => {
+            simulator.load_grids(&scene.matter, &scene.background);
+            camera.pos = scene.camera_pos;
+            camera.scale = scene.camera_scale;
+            for (entity, _) in emitters.iter() {
+                commands.entity(entity).despawn();
+            }
+            for (entity, _) in drains.iter() {
+                commands.entity(entity).despawn();
+            }
+            for &emitter in scene.emitters.iter() {
+                commands.spawn().insert(emitter);
+            }
+            for &drain in scene.drains.iter() {
+                commands.spawn().insert(drain);
+            }
+            bevy::log::info!("Loaded scene {}", scene.name);
+            library.status = Some(Ok(format!("Loaded {}", scene.name)));
+            library.name = scene.name;
+            library.description = scene.description;
+        }
+        Some(SceneRequest::Save(path)) => {
+            if let Some((_, handle)) = library.saving.take() {
+                simulator.cancel_readback(handle);
+            }
+            library.status = Some(Ok(format!("Saving {}", path.display())));
+            library.saving = Some((path, simulator.request_grid_readback()));
+        }
+        None => (),
+    }
+
+    let readback = library
+        .saving
+        .as_ref()
+        .and_then(|&(_, handle)| simulator.poll_readback(handle));
+    if let Some(readback) = readback {
+        let (path, _) = library.saving.take().unwrap();
+        let scene = Scene {
+            name: library.name.clone(),
+            description: library.description.clone(),
+            canvas_size: simulator.canvas_size(),
+            camera_pos: camera.pos,
+            camera_scale: camera.scale,
+            matter: readback.matter,
+            background: readback.background,
+            emitters: emitters.iter().map(|(_, &emitter)| emitter).collect(),
+            drains: drains.iter().map(|(_, &drain)| drain).collect(),
+        };
+        let result = save_scene(&scene, &path).map(|()| format!("Saved {}", path.display()));
+        match &result {
+            Ok(message) => bevy::log::info!("{}", message),
+            Err(e) => bevy::log::error!("{}", e),
+        }
+        library.status = Some(result);
+    }
+}
@@ -0,0 +1,89 @@
+use bevy::{prelude::*, utils::HashMap};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{events::MatterEntered, matter::MatterId, particle_simulator::CASimulator};
+
+// Rectangle of the canvas whose matter is counted on the gpu every step, see MatterEntered
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct Sensor {
+    // Inclusive corners
+    pub min: IVec2,
+    pub max: IVec2,
+}
+
+// Sensor uploaded to the gpu. Must match Sensor in includes.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct SensorRect {
+    min: [i32; 2],
+    max: [i32; 2],
+}
+
+impl From<&Sensor> for SensorRect {
+    fn from(sensor: &Sensor) -> Self {
+        SensorRect {
+            min: sensor.min.to_array(),
+            max: sensor.max.to_array(),
+        }
+    }
+}
+
+// Population of each matter id inside a sensor after a step, indexed by matter id
+#[derive(Debug, Clone)]
+pub struct SensorCounts {
+    pub step: u32,
+    pub sensor: Entity,
+    // Rectangle the counts were taken in, the sensor may have moved since
+    pub rect: SensorRect,
+    pub counts: Vec<u32>,
+}
+
+// Gpu sensors of all sensor entities
+pub fn collect_sensors(sensors: &Query<(Entity, &Sensor)>) -> Vec<(Entity, SensorRect)> {
+    sensors
+        .iter()
+        .map(|(entity, sensor)| (entity, SensorRect::from(sensor)))
+        .collect()
+}
+
+// Send MatterEntered for every material whose count inside a sensor grew since the sensor's
+// previous reading. The first reading of a sensor, and of a sensor that was moved or resized,
+// only sets its baseline. Readings of a sensor's old rectangle are dropped.
+pub fn emit_sensor_events(
+    mut simulator: ResMut<CASimulator>,
+    sensors: Query<(Entity, &Sensor)>,
+    changed: Query<Entity, Changed<Sensor>>,
+    mut previous: Local<HashMap<Entity, Vec<u32>>>,
+    mut events: EventWriter<MatterEntered>,
+) {
+    for entity in changed.iter() {
+        previous.remove(&entity);
+    }
+    for reading in simulator.poll_sensor_counts() {
+        let is_current = sensors.get(reading.sensor).map_or(false, |(_, sensor)| {
+            SensorRect::from(sensor) == reading.rect
+        });
+        if !is_current {
+            continue;
+        }
+        if let Some(previous_counts) = previous.get(&reading.sensor) {
+            for (id, (&count, &previous_count)) in
+                reading.counts.iter().zip(previous_counts).enumerate()
+            {
+                if count <= previous_count {
+                    continue;
+                }
+                if let Some(material) = MatterId::from_id(id as u8) {
+                    events.send(MatterEntered {
+                        sensor: reading.sensor,
+                        material,
+                        count: count - previous_count,
+                    });
+                }
+            }
+        }
+        previous.insert(reading.sensor, reading.counts);
+    }
+    // Forget despawned sensors
+    previous.retain(|&entity, _| sensors.get(entity).is_ok());
+}
@@ -0,0 +1,115 @@
+use std::{fs, path::Path, time::SystemTime};
+
+use bevy::prelude::*;
+use shaderc::{CompileOptions, Compiler, ResolvedInclude, ShaderKind};
+use strum::IntoEnumIterator;
+
+use crate::particle_simulator::{CASimulator, ReloadableShader};
+
+// Must match COMPUTE_SHADER_DIR in build.rs
+pub const COMPUTE_SHADER_DIR: &str = "compute_shaders";
+// Seconds between checks for changed shader files
+const CHECK_INTERVAL: f64 = 0.5;
+
+// Recompiles compute shaders when files in COMPUTE_SHADER_DIR change, so rules can be iterated
+// without restarting. Only the body of a shader can change, shaders with other bindings or push
+// constants than at build time fail to reload and the old pipeline keeps running.
+pub struct ShaderReload {
+    compiler: Option<Compiler>,
+    last_modified: Option<SystemTime>,
+    last_check: f64,
+    // Compile errors of the latest reload, shown in the gui
+    pub errors: Vec<String>,
+}
+
+impl Default for ShaderReload {
+    fn default() -> Self {
+        let compiler = Compiler::new();
+        if compiler.is_none() {
+            bevy::log::error!("Failed to create shader compiler, shader reloading is disabled");
+        }
+        Self {
+            compiler,
+            last_modified: latest_modification(Path::new(COMPUTE_SHADER_DIR)),
+            last_check: 0.0,
+            errors: vec![],
+        }
+    }
+}
+
+impl ShaderReload {
+    // Compile a shader to spir-v, resolving includes from COMPUTE_SHADER_DIR
+    fn compile(&self, shader: ReloadableShader) -> Result<Vec<u32>, String> {
+        let compiler = self
+            .compiler
+            .as_ref()
+            .ok_or_else(|| "No shader compiler".to_string())?;
+        let path = Path::new(COMPUTE_SHADER_DIR).join(shader.file_name());
+        let source = read_source(&path)?;
+        let mut options =
+            CompileOptions::new().ok_or_else(|| "Failed to create compile options".to_string())?;
+        options.set_include_callback(|name, _, _, _| {
+            let include_path = Path::new(COMPUTE_SHADER_DIR).join(name);
+            Ok(ResolvedInclude {
+                resolved_name: include_path.to_string_lossy().into_owned(),
+                content: read_source(&include_path)?,
+            })
+        });
+        let artifact = compiler
+            .compile_into_spirv(
+                &source,
+                ShaderKind::Compute,
+                shader.file_name(),
+                "main",
+                Some(&options),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(artifact.as_binary().to_vec())
+    }
+}
+
+fn read_source(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+// Latest modification time of the files in a directory
+fn latest_modification(dir: &Path) -> Option<SystemTime> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .max()
+}
+
+// System that rebuilds the reloadable pipelines once any shader file has changed. Includes are
+// shared, so every reloadable shader is recompiled. Shaders that fail keep their old pipeline.
+pub fn reload_shaders(
+    time: Res<Time>,
+    mut reload: ResMut<ShaderReload>,
+    mut simulator: ResMut<CASimulator>,
+) {
+    let now = time.seconds_since_startup();
+    if now - reload.last_check < CHECK_INTERVAL {
+        return;
+    }
+    reload.last_check = now;
+    let modified = latest_modification(Path::new(COMPUTE_SHADER_DIR));
+    if modified == reload.last_modified {
+        return;
+    }
+    reload.last_modified = modified;
+
+    let mut errors = vec![];
+    for shader in ReloadableShader::iter() {
+        let result = reload
+            .compile(shader)
+            .and_then(|spirv| simulator.reload_shader(shader, &spirv));
+        match result {
+            Ok(()) => bevy::log::info!("Reloaded {}", shader.file_name()),
+            Err(e) => {
+                bevy::log::error!("Failed to reload {}: {}", shader.file_name(), e);
+                errors.push(format!("{}: {}", shader.file_name(), e));
+            }
+        }
+    }
+    reload.errors = errors;
+}
@@ -0,0 +1,292 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_vulkano::BevyVulkanoWindows;
+use strum_macros::EnumIter;
+
+use crate::{
+    matter::{MatterId, MatterWithColor},
+    minimap::Minimap,
+    particle_simulator::{CASimulator, GridReadback, ReadbackHandle},
+    CurrentMousePos, DynamicSettings, Tool,
+};
+
+// Where stamps are saved and listed from
+pub const STAMPS_DIR: &str = "stamps";
+
+// How pasted cells treat matter already in the grid
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PasteMode {
+    // Only fill empty cells, keeping existing matter
+    OnlyEmpty,
+    Overwrite,
+}
+
+// Rectangle of matter values copied out of the grid. Rows are stored bottom to top like the grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamp {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<u32>,
+}
+
+impl Stamp {
+    pub fn new(width: u32, height: u32, cells: Vec<u32>) -> Stamp {
+        assert_eq!(cells.len(), (width * height) as usize);
+        Stamp {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> u32 {
+        self.cells[(y * self.width + x) as usize]
+    }
+
+    fn map_cells(&self, width: u32, height: u32, source: impl Fn(u32, u32) -> (u32, u32)) -> Stamp {
+        let cells = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (source_x, source_y) = source(x, y);
+                self.get(source_x, source_y)
+            })
+            .collect();
+        Stamp::new(width, height, cells)
+    }
+
+    // Rotated a quarter turn clockwise
+    pub fn rotated(&self) -> Stamp {
+        let width = self.width;
+        self.map_cells(self.height, self.width, |x, y| (width - 1 - y, x))
+    }
+
+    pub fn flipped_horizontal(&self) -> Stamp {
+        let width = self.width;
+        self.map_cells(self.width, self.height, |x, y| (width - 1 - x, y))
+    }
+
+    pub fn flipped_vertical(&self) -> Stamp {
+        let height = self.height;
+        self.map_cells(self.width, self.height, |x, y| (x, height - 1 - y))
+    }
+
+    // Text art with one matter symbol per cell and the top row first
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let matter_id = MatterWithColor::from(self.get(x, y)).matter_id();
+                text.push(MatterId::from_id(matter_id).map_or('?', |matter| matter.symbol()));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Parse text art written by `to_text`. Every row must have the same width.
+    pub fn from_text(text: &str) -> Result<Stamp, String> {
+        let rows = text
+            .lines()
+            .map(|line| line.trim_end())
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .collect::<Vec<_>>();
+        let width = rows.first().map_or(0, |(_, row)| row.chars().count());
+        if width == 0 {
+            return Err("stamp is empty".to_string());
+        }
+        let mut cells = vec![];
+        for &(index, row) in rows.iter().rev() {
+            let line_number = index + 1;
+            if row.chars().count() != width {
+                return Err(format!(
+                    "line {}: expected {} cells, got {}",
+                    line_number,
+                    width,
+                    row.chars().count()
+                ));
+            }
+            for symbol in row.chars() {
+                let matter = MatterId::from_symbol(symbol).ok_or_else(|| {
+                    format!("line {}: unknown matter symbol '{}'", line_number, symbol)
+                })?;
+                cells.push(MatterWithColor::new(matter).value);
+            }
+        }
+        Ok(Stamp::new(width as u32, rows.len() as u32, cells))
+    }
+}
+
+// Save a stamp as STAMPS_DIR/<name>.txt
+pub fn save_stamp(stamp: &Stamp, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains(|c: char| std::path::is_separator(c) || c == '.') {
+        return Err(format!("Invalid stamp name '{}'", name));
+    }
+    fs::create_dir_all(STAMPS_DIR)
+        .map_err(|e| format!("Failed to create {}: {}", STAMPS_DIR, e))?;
+    let path = Path::new(STAMPS_DIR).join(format!("{}.txt", name));
+    fs::write(&path, stamp.to_text())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn load_stamp(path: impl AsRef<Path>) -> Result<Stamp, String> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Stamp::from_text(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Stamp files in STAMPS_DIR sorted by name
+pub fn stamp_files() -> Vec<PathBuf> {
+    let mut files = fs::read_dir(STAMPS_DIR).map_or_else(
+        |_| vec![],
+        |entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "txt"))
+                .collect()
+        },
+    );
+    files.sort();
+    files
+}
+
+// Selected region, the stamp being pasted and the state of the stamp library
+pub struct Clipboard {
+    // Inclusive corners of the selected cells
+    pub selection: Option<(IVec2, IVec2)>,
+    drag_start: Option<IVec2>,
+    pub stamp: Option<Stamp>,
+    pub paste_mode: PasteMode,
+    // Name the stamp is saved under
+    pub stamp_name: String,
+    // Copied selection waiting for the gpu to read it back
+    copying: Option<((IVec2, IVec2), ReadbackHandle)>,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            selection: None,
+            drag_start: None,
+            stamp: None,
+            paste_mode: PasteMode::OnlyEmpty,
+            stamp_name: String::new(),
+            copying: None,
+        }
+    }
+}
+
+// Stamp of a selection with inclusive corners. Cells outside the canvas are empty.
+fn selection_stamp(min: IVec2, max: IVec2, readback: Option<&GridReadback>) -> Stamp {
+    let size = max - min + IVec2::ONE;
+    let cells = (min.y..=max.y)
+        .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+        .map(|pos| {
+            readback
+                .and_then(|readback| readback.matter_at(pos))
+                .unwrap_or(0)
+        })
+        .collect();
+    Stamp::new(size.x as u32, size.y as u32, cells)
+}
+
+impl Clipboard {
+    // Copy the selected cells into the stamp. The stamp is replaced once the gpu has read the
+    // cells back, see `receive_copy`.
+    pub fn copy(&mut self, simulator: &mut CASimulator) {
+        if let Some((min, max)) = self.selection {
+            if let Some((_, handle)) = self.copying.take() {
+                simulator.cancel_readback(handle);
+            }
+            match simulator.request_readback(min, max) {
+                Some(handle) => self.copying = Some(((min, max), handle)),
+                None => self.stamp = Some(selection_stamp(min, max, None)),
+            }
+        }
+    }
+
+    // Take the copied stamp once it's read back
+    fn receive_copy(&mut self, simulator: &mut CASimulator) {
+        if let Some(((min, max), handle)) = self.copying {
+            if let Some(readback) = simulator.poll_readback(handle) {
+                self.stamp = Some(selection_stamp(min, max, Some(&readback)));
+                self.copying = None;
+            }
+        }
+    }
+}
+
+// Lower left corner of a stamp pasted centered on a position
+fn paste_origin(stamp: &Stamp, center: IVec2) -> IVec2 {
+    center - IVec2::new(stamp.width as i32 / 2, stamp.height as i32 / 2)
+}
+
+// Drag a selection with the select tool, copy it with ctrl + c and paste with the paste tool.
+// The selection, or the stamp under the cursor, is outlined on the canvas.
+pub fn select_and_paste(
+    mut simulator: ResMut<CASimulator>,
+    mut clipboard: ResMut<Clipboard>,
+    windows: Res<Windows>,
+    current: Res<CurrentMousePos>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<DynamicSettings>,
+    minimap: Res<Minimap>,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+) {
+    let over_minimap = minimap
+        .cursor_to_world(windows.get_primary().unwrap())
+        .is_some();
+    let cursor = current.0.filter(|_| !over_minimap).map(|current| {
+        let canvas_pos = current.canvas_pos().round();
+        IVec2::new(canvas_pos.x as i32, canvas_pos.y as i32)
+    });
+    let ctrl =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    // Text fields with keyboard focus handle ctrl + c themselves
+    let gui_wants_keyboard = vulkano_windows
+        .get_primary_window_renderer()
+        .map_or(false, |(_, gui)| gui.context().wants_keyboard_input());
+    if ctrl && keyboard_input.just_pressed(KeyCode::C) && !gui_wants_keyboard {
+        clipboard.copy(&mut simulator);
+    }
+    clipboard.receive_copy(&mut simulator);
+
+    let mut outline = None;
+    match settings.tool {
+        Tool::Select => {
+            if let Some(cursor) = cursor {
+                if mouse_button_input.just_pressed(MouseButton::Left) {
+                    clipboard.drag_start = Some(cursor);
+                }
+                if let Some(start) = clipboard.drag_start {
+                    if mouse_button_input.pressed(MouseButton::Left) {
+                        clipboard.selection = Some((start.min(cursor), start.max(cursor)));
+                    }
+                }
+            }
+            if !mouse_button_input.pressed(MouseButton::Left) {
+                clipboard.drag_start = None;
+            }
+            outline = clipboard.selection;
+        }
+        Tool::Paste => {
+            if let (Some(cursor), Some(stamp)) = (cursor, clipboard.stamp.as_ref()) {
+                let origin = paste_origin(stamp, cursor);
+                if mouse_button_input.just_pressed(MouseButton::Left) {
+                    simulator.paste_stamp(origin, stamp, clipboard.paste_mode);
+                }
+                let size = IVec2::new(stamp.width as i32, stamp.height as i32);
+                outline = Some((origin, origin + size - IVec2::ONE));
+            }
+        }
+        _ => (),
+    }
+    simulator.set_selection_outline(outline);
+}
@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{matter::MatterCounts, particle_simulator::CASimulator};
+
+// How many steps of population counts are kept for plotting
+pub const MATTER_COUNT_HISTORY_LEN: usize = 600;
+
+// Population counts of the latest simulation steps, oldest first
+#[derive(Default)]
+pub struct MatterCountHistory {
+    pub samples: VecDeque<MatterCounts>,
+}
+
+impl MatterCountHistory {
+    pub fn latest(&self) -> Option<&MatterCounts> {
+        self.samples.back()
+    }
+
+    fn push(&mut self, counts: MatterCounts) {
+        if self.samples.len() == MATTER_COUNT_HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(counts);
+    }
+}
+
+// Collect population counts that the gpu has finished
+pub fn record_matter_counts(
+    mut simulator: ResMut<CASimulator>,
+    mut history: ResMut<MatterCountHistory>,
+) {
+    if let Some(counts) = simulator.poll_matter_counts() {
+        let is_new = history
+            .latest()
+            .map_or(true, |latest| latest.step != counts.step);
+        if is_new {
+            history.push(counts.clone());
+        }
+    }
+}
@@ -20,8 +20,6 @@ use vulkano::{
     shader::{EntryPoint, ShaderStages, SpecializationConstants},
 };
 
-use crate::{CANVAS_SIZE_X, CANVAS_SIZE_Y};
-
 pub const EMPTY_COLOR: u32 = 0x0;
 
 // Creates a descriptor set for sampled image descriptor set using nearest sampling. This means that the image
@@ -122,17 +120,22 @@ pub fn cursor_to_world(window: &Window, camera_pos: Vec2, camera_scale: f32) ->
 #[derive(Debug, Copy, Clone)]
 pub struct MousePos {
     pub world: Vec2,
+    // Half the canvas size, the offset from world to canvas position
+    canvas_offset: Vec2,
 }
 
 impl MousePos {
-    pub fn new(pos: Vec2) -> MousePos {
-        MousePos { world: pos }
+    pub fn new(pos: Vec2, canvas_size: UVec2) -> MousePos {
+        MousePos {
+            world: pos,
+            canvas_offset: canvas_size.as_vec2() / 2.0,
+        }
     }
 
     // Converts world position to canvas position:
     // Inverts y and adds half canvas to the position (pixel units)
     pub fn canvas_pos(&self) -> Vec2 {
-        self.world + Vec2::new(CANVAS_SIZE_X as f32 / 2.0, CANVAS_SIZE_Y as f32 / 2.0)
+        self.world + self.canvas_offset
     }
 }
 
@@ -153,6 +156,24 @@ pub fn get_canvas_line(prev: Option<MousePos>, current: MousePos) -> Vec<IVec2>
     .collect::<Vec<IVec2>>()
 }
 
+// Inclusive corners of the cells a brush covers along a line, clamped to the canvas. Line
+// points outside the canvas are skipped like in CASimulator::draw_matter. None if the whole
+// line is outside.
+pub fn brush_bounds(line: &[IVec2], radius: f32, canvas_size: UVec2) -> Option<(IVec2, IVec2)> {
+    let canvas_max = canvas_size.as_ivec2() - IVec2::ONE;
+    let (min, max) = line
+        .iter()
+        .filter(|pos| pos.cmpge(IVec2::ZERO).all() && pos.cmple(canvas_max).all())
+        .fold(None, |bounds: Option<(IVec2, IVec2)>, &pos| {
+            Some(bounds.map_or((pos, pos), |(min, max)| (min.min(pos), max.max(pos))))
+        })?;
+    let radius = IVec2::splat(radius as i32);
+    Some((
+        (min - radius).max(IVec2::ZERO),
+        (max + radius).min(canvas_max),
+    ))
+}
+
 //color data conversion
 
 // Converts u32 color to array of 4 u8
@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use strum_macros::EnumIter;
+
+use crate::{
+    matter::{MatterId, MatterWithColor},
+    minimap::Minimap,
+    CurrentMousePos, DynamicSettings, Tool,
+};
+
+// Must match world object kinds in world_objects.glsl
+const WORLD_OBJECT_EMITTER: u32 = 0;
+const WORLD_OBJECT_DRAIN: u32 = 1;
+const SHAPE_CIRCLE: u32 = 0;
+const SHAPE_RECT: u32 = 1;
+
+// Area of a world object in canvas coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+    Circle { center: IVec2, radius: i32 },
+    // Inclusive corners
+    Rect { min: IVec2, max: IVec2 },
+}
+
+impl Shape {
+    // Number of cells inside the shape (approximately for circles)
+    pub fn area(&self) -> f32 {
+        match *self {
+            Shape::Circle { radius, .. } => {
+                (std::f32::consts::PI * (radius * radius) as f32).max(1.0)
+            }
+            Shape::Rect { min, max } => {
+                let size = (max - min + IVec2::ONE).max(IVec2::ZERO);
+                (size.x * size.y) as f32
+            }
+        }
+    }
+}
+
+// Shape of emitters and drains placed with the mouse
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ShapeKind {
+    // Brush radius around the click
+    Circle,
+    // Rectangle dragged with the mouse
+    Rect,
+}
+
+// Spawns matter into empty cells of its shape each step
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Emitter {
+    pub matter: MatterId,
+    pub shape: Shape,
+    // Expected number of cells spawned per step
+    pub rate: f32,
+}
+
+// Deletes any matter inside its shape each step
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Drain {
+    pub shape: Shape,
+}
+
+// World object uploaded to the gpu. Must match WorldObject in includes.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+pub struct WorldObject {
+    kind: u32,
+    shape: u32,
+    // Matter value spawned by emitters
+    matter: u32,
+    // Chance of an empty cell to be spawned into per step
+    probability: f32,
+    // Circle center or rect min
+    min: [i32; 2],
+    // Circle radius in x or rect max
+    max: [i32; 2],
+}
+
+impl WorldObject {
+    fn new(kind: u32, shape: Shape, matter: u32, probability: f32) -> WorldObject {
+        let (shape, min, max) = match shape {
+            Shape::Circle { center, radius } => (SHAPE_CIRCLE, center, IVec2::new(radius, 0)),
+            Shape::Rect { min, max } => (SHAPE_RECT, min, max),
+        };
+        WorldObject {
+            kind,
+            shape,
+            matter,
+            probability,
+            min: min.to_array(),
+            max: max.to_array(),
+        }
+    }
+}
+
+impl From<&Emitter> for WorldObject {
+    fn from(emitter: &Emitter) -> Self {
+        WorldObject::new(
+            WORLD_OBJECT_EMITTER,
+            emitter.shape,
+            MatterWithColor::new(emitter.matter).value,
+            (emitter.rate / emitter.shape.area()).min(1.0),
+        )
+    }
+}
+
+impl From<&Drain> for WorldObject {
+    fn from(drain: &Drain) -> Self {
+        WorldObject::new(WORLD_OBJECT_DRAIN, drain.shape, 0, 0.0)
+    }
+}
+
+// Gpu world objects of all emitters and drains
+pub fn collect_world_objects(
+    emitters: &Query<&Emitter>,
+    drains: &Query<&Drain>,
+) -> Vec<WorldObject> {
+    emitters
+        .iter()
+        .map(WorldObject::from)
+        .chain(drains.iter().map(WorldObject::from))
+        .collect()
+}
+
+// Place emitters and drains with left click when their tool is selected. Circles are centered
+// on the click, rectangles are dragged.
+pub fn place_world_objects(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    current: Res<CurrentMousePos>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    settings: Res<DynamicSettings>,
+    minimap: Res<Minimap>,
+    mut drag_start: Local<Option<IVec2>>,
+) {
+    if !matches!(settings.tool, Tool::Emitter | Tool::Drain) {
+        *drag_start = None;
+        return;
+    }
+    let over_minimap = minimap
+        .cursor_to_world(windows.get_primary().unwrap())
+        .is_some();
+    let cursor = current.0.filter(|_| !over_minimap).map(|current| {
+        let canvas_pos = current.canvas_pos().round();
+        IVec2::new(canvas_pos.x as i32, canvas_pos.y as i32)
+    });
+    let shape = match settings.world_object_shape {
+        ShapeKind::Circle => match cursor {
+            Some(center) if mouse_button_input.just_pressed(MouseButton::Left) => Shape::Circle {
+                center,
+                radius: settings.brush_radius as i32,
+            },
+            _ => return,
+        },
+        ShapeKind::Rect => {
+            if mouse_button_input.just_pressed(MouseButton::Left) {
+                *drag_start = cursor;
+            }
+            if !mouse_button_input.just_released(MouseButton::Left) {
+                return;
+            }
+            match (drag_start.take(), cursor) {
+                (Some(start), Some(end)) => Shape::Rect {
+                    min: start.min(end),
+                    max: start.max(end),
+                },
+                _ => return,
+            }
+        }
+    };
+    match settings.tool {
+        Tool::Emitter => {
+            commands.spawn().insert(Emitter {
+                matter: settings.draw_matter,
+                shape,
+                rate: settings.emitter_rate,
+            });
+        }
+        Tool::Drain => {
+            commands.spawn().insert(Drain { shape });
+        }
+        Tool::Brush | Tool::Background | Tool::Explode | Tool::Select | Tool::Paste => (),
+    }
+}
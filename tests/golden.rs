@@ -0,0 +1,219 @@
+// Golden state tests of the movement rules. Each case in GOLDEN_DIR has a `steps <N>` line, an
+// `input` section and an `expected` section of text art (see `Stamp::from_text`). The input is
+// simulated for N steps and compared with the expected grid. Run with UPDATE_GOLDEN=1 to write
+// the simulated grids as the expected ones, and with GOLDEN_SOFTWARE_DEVICE=1 to simulate on a cpu
+// Vulkan implementation such as lavapipe. Without any Vulkan device the cases are skipped.
+// Cases are read relative to the package root, where cargo runs tests.
+use std::{fs, path::Path};
+
+use bevy::math::{IVec2, UVec2};
+use vulkano::{device::physical::PhysicalDevice, instance::Instance};
+use vulkano_util::context::VulkanoContext;
+
+use particle_simulation::{
+    background::BackgroundId,
+    matter::{MatterId, MatterWithColor},
+    particle_simulator::CASimulator,
+    stamps::Stamp,
+    Config,
+};
+
+const GOLDEN_DIR: &str = "tests/golden";
+const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+const SOFTWARE_DEVICE_ENV: &str = "GOLDEN_SOFTWARE_DEVICE";
+// A single work group, large enough for every case
+const CANVAS_SIZE: u32 = 32;
+
+struct GoldenCase {
+    steps: u32,
+    input: Stamp,
+    expected: Option<Stamp>,
+}
+
+fn parse_case(text: &str) -> Result<GoldenCase, String> {
+    let mut steps = None;
+    let mut section = None;
+    let mut input = String::new();
+    let mut expected = String::new();
+    for line in text.lines().map(|line| line.trim_end()) {
+        if let Some(value) = line.strip_prefix("steps ") {
+            let parsed = value.trim().parse::<u32>();
+            steps = Some(parsed.map_err(|_| format!("invalid step count '{}'", value))?);
+        } else if line == "input" || line == "expected" {
+            section = Some(line);
+        } else {
+            let art = match section {
+                Some("input") => &mut input,
+                Some(_) => &mut expected,
+                None if line.is_empty() => continue,
+                None => return Err(format!("'{}' before the input section", line)),
+            };
+            art.push_str(line);
+            art.push('\n');
+        }
+    }
+    let steps = steps.ok_or("missing steps line")?;
+    let input = Stamp::from_text(&input).map_err(|e| format!("input: {}", e))?;
+    let expected = if expected.trim().is_empty() {
+        None
+    } else {
+        Some(Stamp::from_text(&expected).map_err(|e| format!("expected: {}", e))?)
+    };
+    Ok(GoldenCase {
+        steps,
+        input,
+        expected,
+    })
+}
+
+fn write_case(path: &Path, steps: u32, input: &Stamp, expected: &Stamp) {
+    let text = format!(
+        "steps {}\ninput\n{}expected\n{}",
+        steps,
+        input.to_text(),
+        expected.to_text()
+    );
+    fs::write(path, text).unwrap_or_else(|e| panic!("Failed to write {}: {}", path.display(), e));
+}
+
+// Context on the device the simulation would use, see Config::vulkano_config. None without
+// such a device, since VulkanoContext::new panics then.
+fn vulkan_context() -> Option<VulkanoContext> {
+    let mut config = Config::default();
+    config.software_device = std::env::var_os(SOFTWARE_DEVICE_ENV).is_some();
+    let probe = config.vulkano_config();
+    let instance = Instance::new(probe.instance_create_info).ok()?;
+    if !PhysicalDevice::enumerate(&instance).any(|device| (probe.device_filter_fn)(&device)) {
+        return None;
+    }
+    Some(VulkanoContext::new(config.vulkano_config()))
+}
+
+// Simulate a case's input in the lower left corner of the canvas. Cells outside the input are
+// rock, which doesn't move without reactions, so matter can't leave the input's rectangle.
+fn simulate(context: &VulkanoContext, input: &Stamp, steps: u32) -> Stamp {
+    assert!(input.width <= CANVAS_SIZE && input.height <= CANVAS_SIZE);
+    let canvas_size = UVec2::new(CANVAS_SIZE, CANVAS_SIZE);
+    let mut simulator = CASimulator::new(context.compute_queue(), canvas_size);
+    let rock = MatterWithColor::new(MatterId::Rock).value;
+    let matter = (0..CANVAS_SIZE)
+        .flat_map(|y| (0..CANVAS_SIZE).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            if x < input.width && y < input.height {
+                input.get(x, y)
+            } else {
+                rock
+            }
+        })
+        .collect::<Vec<_>>();
+    let background = vec![BackgroundId::Empty.value(); matter.len()];
+    simulator.load_grids(&matter, &background);
+    for _ in 0..steps {
+        simulator.step(1, false);
+    }
+    simulator.wait_idle();
+    let max = IVec2::new(input.width as i32 - 1, input.height as i32 - 1);
+    simulator.copy_region(IVec2::ZERO, max)
+}
+
+// Expected and actual rows side by side, with differing cells marked below their row
+fn diff(expected: &Stamp, actual: &Stamp) -> String {
+    let expected_text = expected.to_text();
+    let actual_text = actual.to_text();
+    let width = expected.width as usize;
+    let mut text = format!("{:<w$}   actual\n", "expected", w = width.max(8));
+    for (expected_row, actual_row) in expected_text.lines().zip(actual_text.lines()) {
+        text.push_str(&format!(
+            "{:<w$}   {}\n",
+            expected_row,
+            actual_row,
+            w = width.max(8)
+        ));
+        if expected_row != actual_row {
+            let markers = expected_row
+                .chars()
+                .zip(actual_row.chars())
+                .map(|(e, a)| if e == a { ' ' } else { '^' })
+                .collect::<String>();
+            text.push_str(&format!("{}\n", markers.trim_end()));
+        }
+    }
+    text
+}
+
+// Run one case, returning a description of the failure if it doesn't match
+fn check_case(context: &VulkanoContext, path: &Path, update: bool) -> Result<(), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let case = parse_case(&text)?;
+    let actual = simulate(context, &case.input, case.steps);
+    if update {
+        write_case(path, case.steps, &case.input, &actual);
+        return Ok(());
+    }
+    let expected = case
+        .expected
+        .ok_or_else(|| format!("no expected grid, run with {}=1 to write it", UPDATE_ENV))?;
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(format!(
+            "expected grid is {}x{} but the input is {}x{}",
+            expected.width, expected.height, actual.width, actual.height
+        ));
+    }
+    if expected.to_text() != actual.to_text() {
+        return Err(format!(
+            "differs after {} steps\n{}",
+            case.steps,
+            diff(&expected, &actual)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn golden_cases() {
+    let update = std::env::var_os(UPDATE_ENV).is_some();
+    let mut paths = fs::read_dir(GOLDEN_DIR)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", GOLDEN_DIR, e))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "txt"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no cases in {}", GOLDEN_DIR);
+
+    let context = match vulkan_context() {
+        Some(context) => context,
+        None => {
+            eprintln!("Skipping golden cases: no Vulkan device found");
+            return;
+        }
+    };
+    let failures = paths
+        .iter()
+        .filter_map(|path| {
+            check_case(&context, path, update)
+                .err()
+                .map(|e| format!("{}: {}", path.display(), e))
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        failures.is_empty(),
+        "{} of {} golden cases failed\n\n{}",
+        failures.len(),
+        paths.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn parse_case_sections() {
+    let case = parse_case("steps 3\ninput\nS.\n##\nexpected\n..\n#S\n").unwrap();
+    assert_eq!(case.steps, 3);
+    assert_eq!(case.input.to_text(), "S.\n##\n");
+    assert_eq!(case.expected.unwrap().to_text(), "..\n#S\n");
+    assert!(parse_case("input\nS\n").is_err());
+    assert!(parse_case("steps 1\ninput\nS\nexpected\n")
+        .unwrap()
+        .expected
+        .is_none());
+}